@@ -0,0 +1,83 @@
+//! Anything that can fill a seat: a person at the mouse, the CPU, or a
+//! player on the other end of a connection.
+
+use crate::ai::{self, Difficulty};
+use crate::{Game, GameError, Point, Role};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+pub trait Agent {
+    /// The move to play for `game.current_player`, or `None` if the agent
+    /// has not decided yet; it is asked again later.
+    fn choose_move(&mut self, game: &Game) -> Option<Point>;
+
+    /// Hands over a move chosen elsewhere, e.g. one that arrived over the
+    /// network. Agents that decide for themselves ignore it.
+    fn submit(&mut self, _pos: Point) {}
+
+    /// Whether to let the opponent take back a move, or `None` to ask the
+    /// person behind this seat.
+    fn accepts_takeback(&self) -> Option<bool> {
+        None
+    }
+}
+
+pub struct CpuAgent {
+    pub difficulty: Difficulty,
+    rng: StdRng,
+}
+
+impl CpuAgent {
+    pub fn new(difficulty: Difficulty) -> Self {
+        CpuAgent {
+            difficulty,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn seeded(difficulty: Difficulty, seed: u64) -> Self {
+        CpuAgent {
+            difficulty,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Agent for CpuAgent {
+    fn choose_move(&mut self, game: &Game) -> Option<Point> {
+        match game.current_player {
+            Role::Runner => ai::runner_move(game, self.difficulty, &mut self.rng),
+            Role::Blocker => ai::blocker_move(game, self.difficulty, &mut self.rng),
+        }
+    }
+
+    fn accepts_takeback(&self) -> Option<bool> {
+        Some(true)
+    }
+}
+
+/// A seat whose moves come in over the network; each submitted move is
+/// played the next time the seat is asked.
+#[derive(Default)]
+pub struct RemoteAgent {
+    pending: Option<Point>,
+}
+
+impl Agent for RemoteAgent {
+    fn choose_move(&mut self, _game: &Game) -> Option<Point> {
+        self.pending.take()
+    }
+
+    fn submit(&mut self, pos: Point) {
+        self.pending = Some(pos);
+    }
+}
+
+/// Plays `agent`'s move for the current player if it has one, returning
+/// whether a move was made.
+pub fn step(game: &mut Game, agent: &mut dyn Agent) -> Result<bool, GameError> {
+    match agent.choose_move(game) {
+        Some(pos) => game.play(game.current_player, pos).map(|()| true),
+        None => Ok(false),
+    }
+}
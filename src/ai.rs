@@ -0,0 +1,426 @@
+//! Move choice for the CPU player.
+
+use crate::{Game, MovementRule, Point, Role};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+/// How hard the CPU plays.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    /// Random moves around the runner, slightly biased towards the goal.
+    #[default]
+    Easy,
+    /// The best move one ply ahead.
+    Medium,
+    /// Looks at the opponent's reply before choosing.
+    Hard,
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("unknown difficulty {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "easy"),
+            Difficulty::Medium => write!(f, "medium"),
+            Difficulty::Hard => write!(f, "hard"),
+        }
+    }
+}
+
+/// Searches cheaper than this look further ahead on Hard.
+const DEEP_SEARCH_COST: usize = 25_000;
+/// Above this the blocker goes without [`min_cut`], whose graph has an edge
+/// for every move from every square.
+const MAX_CUT_COST: usize = 100_000;
+/// The costliest game the CPU still answers in about a second.
+pub const MAX_SEARCH_COST: usize = 500_000;
+
+/// Roughly what a pass over the area costs: every square of it times the
+/// squares a move from one can reach.
+pub fn search_cost(squares: i16, rule: MovementRule, power: i16) -> usize {
+    let area = squares as usize * squares as usize;
+    let reach = 2 * rule.radius(power) as usize + 1;
+    area * area.min(reach * reach)
+}
+
+fn game_cost(game: &Game) -> usize {
+    search_cost(game.squares, game.rule, game.runner.power)
+}
+
+pub fn runner_move<R: Rng>(game: &Game, difficulty: Difficulty, rng: &mut R) -> Option<Point> {
+    match difficulty {
+        Difficulty::Easy => random_runner_move(game, rng),
+        Difficulty::Medium => greedy_runner_move(game),
+        Difficulty::Hard if game_cost(game) > DEEP_SEARCH_COST => greedy_runner_move(game),
+        Difficulty::Hard => lookahead_runner_move(game),
+    }
+}
+
+/// A random legal move, with goal squares three times as likely as the rest.
+pub fn random_runner_move<R: Rng>(game: &Game, rng: &mut R) -> Option<Point> {
+    let mut possible_moves = Vec::new();
+    for pos in game.runner_moves_from(game.runner.position) {
+        let weight = if game.is_goal(pos) { 3 } else { 1 };
+        for _ in 0..weight {
+            possible_moves.push(pos);
+        }
+    }
+    possible_moves.choose(rng).copied()
+}
+
+/// The legal move closest to the goal. When the goal is cut off this still
+/// returns some legal move so the runner keeps playing.
+pub fn greedy_runner_move(game: &Game) -> Option<Point> {
+    let distances = game.goal_distances();
+    game.runner_moves_from(game.runner.position)
+        .into_iter()
+        .min_by_key(|pos| distances.get(pos).copied().unwrap_or(u32::MAX))
+}
+
+/// Like [`greedy_runner_move`], but assumes the blocker will then drop a block
+/// on the next step of the path and prefers the move that loses the least to it.
+pub fn lookahead_runner_move(game: &Game) -> Option<Point> {
+    let distances = game.goal_distances();
+    let distance = |pos: &Point| distances.get(pos).copied().unwrap_or(u32::MAX);
+
+    let moves = game.runner_moves_from(game.runner.position);
+    if let Some(&exit) = moves.iter().find(|pos| game.is_exit(**pos)) {
+        return Some(exit);
+    }
+
+    moves.into_iter().min_by_key(|&pos| {
+        let here = distance(&pos);
+        if here == u32::MAX {
+            return (u32::MAX, Reverse(0));
+        }
+
+        let mut after = game.clone();
+        after.move_runner(pos);
+        let next_steps: Vec<Point> = after
+            .runner_moves_in_area(pos)
+            .into_iter()
+            .filter(|step| distance(step) == here - 1)
+            .collect();
+
+        let worst = next_steps
+            .iter()
+            .filter(|block| after.is_valid_block(**block))
+            .map(|&block| {
+                let mut reply = after.clone();
+                reply.blocker.blocked_squares.insert(block);
+                reply.goal_distances().get(&pos).copied().unwrap_or(u32::MAX)
+            })
+            .max()
+            .unwrap_or(here);
+        (worst, Reverse(next_steps.len()))
+    })
+}
+
+pub fn blocker_move<R: Rng>(game: &Game, difficulty: Difficulty, rng: &mut R) -> Option<Point> {
+    match difficulty {
+        Difficulty::Easy => random_blocker_move(game, rng),
+        Difficulty::Medium => search_blocker_move(game, 1),
+        Difficulty::Hard if game_cost(game) > DEEP_SEARCH_COST => search_blocker_move(game, 1),
+        Difficulty::Hard => search_blocker_move(game, 3),
+    }
+}
+
+/// A random block within the runner's reach, goal squares three times as
+/// likely, or anywhere in the area once the runner's surroundings are full.
+pub fn random_blocker_move<R: Rng>(game: &Game, rng: &mut R) -> Option<Point> {
+    let mut possible_blocks = Vec::new();
+    for pos in game.runner_moves_from(game.runner.position) {
+        let weight = if game.is_goal(pos) { 3 } else { 1 };
+        for _ in 0..weight {
+            possible_blocks.push(pos);
+        }
+    }
+
+    if possible_blocks.is_empty() {
+        possible_blocks.extend(game.area().filter(|pos| game.is_valid_block(*pos)));
+    }
+    possible_blocks.choose(rng).copied()
+}
+
+const WIN: i32 = 1_000_000;
+const SEALED: i32 = WIN / 2;
+const BRANCHING: usize = 8;
+
+/// Alpha-beta search over the blocker's most promising blocks and the
+/// runner's replies, `depth` plies deep.
+pub fn search_blocker_move(game: &Game, depth: u32) -> Option<Point> {
+    let mut best = None;
+    let mut alpha = -WIN - 1;
+    for block in blocker_candidates(game) {
+        let mut next = game.clone();
+        next.move_blocker(block);
+        let score = alpha_beta(&next, depth - 1, alpha, WIN + 1);
+        if best.is_none() || score > alpha {
+            alpha = score;
+            best = Some(block);
+        }
+    }
+    best.or_else(|| random_blocker_move(game, &mut rand::thread_rng()))
+}
+
+/// The position's value for the blocker; higher is better.
+fn alpha_beta(game: &Game, depth: u32, mut alpha: i32, mut beta: i32) -> i32 {
+    if game.won {
+        return -WIN;
+    }
+    if game.game_over {
+        return WIN;
+    }
+    if depth == 0 {
+        return evaluate(game);
+    }
+
+    match game.current_player {
+        Role::Blocker => {
+            let mut value = -WIN - 1;
+            for block in blocker_candidates(game) {
+                let mut next = game.clone();
+                next.move_blocker(block);
+                value = value.max(alpha_beta(&next, depth - 1, alpha, beta));
+                alpha = alpha.max(value);
+                if alpha >= beta {
+                    break;
+                }
+            }
+            if value < -WIN {
+                evaluate(game)
+            } else {
+                value
+            }
+        }
+        Role::Runner => {
+            let distances = game.goal_distances();
+            let mut moves = game.runner_moves_in_area(game.runner.position);
+            moves.sort_by_key(|pos| distances.get(pos).copied().unwrap_or(u32::MAX));
+            moves.truncate(BRANCHING);
+
+            let mut value = WIN + 1;
+            for pos in moves {
+                let mut next = game.clone();
+                next.move_runner(pos);
+                value = value.min(alpha_beta(&next, depth - 1, alpha, beta));
+                beta = beta.min(value);
+                if alpha >= beta {
+                    break;
+                }
+            }
+            if value > WIN {
+                WIN
+            } else {
+                value
+            }
+        }
+    }
+}
+
+/// Far from the goal with few squares left to wall off is good for the blocker.
+fn evaluate(game: &Game) -> i32 {
+    let start = game.runner.position;
+    let Some(&distance) = game.goal_distances().get(&start) else {
+        return SEALED - game.runner_moves_in_area(start).len() as i32;
+    };
+    10 * distance as i32 - 6 * min_cut(game, start).len() as i32
+}
+
+/// Squares on a minimum cut between the runner and the goal, then the
+/// runner's next steps towards it, nearest first.
+fn blocker_candidates(game: &Game) -> Vec<Point> {
+    let start = game.runner.position;
+    let distances = game.goal_distances();
+    let mut candidates = match distances.get(&start) {
+        Some(&distance) => {
+            let mut candidates = min_cut(game, start);
+            candidates.extend(
+                game.runner_moves_in_area(start)
+                    .into_iter()
+                    .filter(|pos| distances.get(pos).is_some_and(|d| *d + 1 == distance)),
+            );
+            candidates
+        }
+        None => game.runner_moves_in_area(start),
+    };
+
+    let reach = |pos: &Point| (pos.0 - start.0).abs().max((pos.1 - start.1).abs());
+    candidates.retain(|pos| game.is_valid_block(*pos));
+    candidates.sort_by_key(|pos| (reach(pos), *pos));
+    candidates.dedup();
+    candidates.truncate(BRANCHING);
+    candidates
+}
+
+/// The smallest set of free squares whose blocking cuts `from` off from the
+/// exits, found as a maximum flow with every square of the area split into
+/// an in and an out node of capacity one. Empty when the area and the
+/// runner's reach make the graph too big to build.
+pub fn min_cut(game: &Game, from: Point) -> Vec<Point> {
+    if game.is_exit(from) || !game.in_area(from) || game_cost(game) > MAX_CUT_COST {
+        return Vec::new();
+    }
+    let size = game.squares as usize;
+    let origin = game.origin();
+    let index = |pos: Point| (pos.1 - origin.1) as usize * size + (pos.0 - origin.0) as usize;
+    let nodes = 2 * size * size + 1;
+    let sink = nodes - 1;
+
+    let mut graph = FlowGraph::new(nodes);
+    for pos in game.area() {
+        if game.is_blocked(pos) {
+            continue;
+        }
+        let i = index(pos);
+        let capacity = if pos == from { u32::MAX } else { 1 };
+        graph.add_edge(2 * i, 2 * i + 1, capacity);
+        if game.is_exit(pos) {
+            // Nothing past an exit matters; leaving out its steps keeps the
+            // graph small when the power is large.
+            graph.add_edge(2 * i + 1, sink, u32::MAX);
+            continue;
+        }
+        for step in game.runner_moves_in_area(pos) {
+            graph.add_edge(2 * i + 1, 2 * index(step), u32::MAX);
+        }
+    }
+
+    let source = 2 * index(from);
+    while graph.augment(source, sink) {}
+
+    let reached = graph.reachable(source);
+    game.area()
+        .filter(|&pos| {
+            let i = index(pos);
+            reached[2 * i] && !reached[2 * i + 1]
+        })
+        .collect()
+}
+
+struct FlowGraph {
+    edges: Vec<(usize, u32)>,
+    adjacent: Vec<Vec<usize>>,
+}
+
+impl FlowGraph {
+    fn new(nodes: usize) -> Self {
+        FlowGraph {
+            edges: Vec::new(),
+            adjacent: vec![Vec::new(); nodes],
+        }
+    }
+
+    /// Edge `e` and its residual twin are stored at `e` and `e ^ 1`.
+    fn add_edge(&mut self, from: usize, to: usize, capacity: u32) {
+        self.adjacent[from].push(self.edges.len());
+        self.edges.push((to, capacity));
+        self.adjacent[to].push(self.edges.len());
+        self.edges.push((from, 0));
+    }
+
+    fn augment(&mut self, source: usize, sink: usize) -> bool {
+        let mut parent = vec![None; self.adjacent.len()];
+        let mut queue = VecDeque::from([source]);
+        while let Some(node) = queue.pop_front() {
+            if node == sink {
+                break;
+            }
+            for &edge in &self.adjacent[node] {
+                let (to, capacity) = self.edges[edge];
+                if capacity > 0 && to != source && parent[to].is_none() {
+                    parent[to] = Some(edge);
+                    queue.push_back(to);
+                }
+            }
+        }
+
+        if parent[sink].is_none() {
+            return false;
+        }
+        // Every path crosses a capacity-one square, so it carries one unit.
+        let mut node = sink;
+        while let Some(edge) = parent[node] {
+            self.edges[edge].1 -= 1;
+            self.edges[edge ^ 1].1 += 1;
+            node = self.edges[edge ^ 1].0;
+        }
+        true
+    }
+
+    fn reachable(&self, source: usize) -> Vec<bool> {
+        let mut reached = vec![false; self.adjacent.len()];
+        reached[source] = true;
+        let mut queue = VecDeque::from([source]);
+        while let Some(node) = queue.pop_front() {
+            for &edge in &self.adjacent[node] {
+                let (to, capacity) = self.edges[edge];
+                if capacity > 0 && !reached[to] {
+                    reached[to] = true;
+                    queue.push_back(to);
+                }
+            }
+        }
+        reached
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GameState, Goal};
+
+    /// A 9x9 board with the runner in the middle of a ring of blocks that
+    /// has a gap at (4, 2).
+    fn walled_in() -> Game {
+        let mut game = Game::new(9, 1, Goal::AnyEdge);
+        game.game_state = GameState::Playing;
+        game.current_player = Role::Blocker;
+        for x in 2..=6 {
+            for y in 2..=6 {
+                if (x == 2 || x == 6 || y == 2 || y == 6) && (x, y) != (4, 2) {
+                    game.blocker.blocked_squares.insert((x, y));
+                }
+            }
+        }
+        game
+    }
+
+    #[test]
+    fn min_cut_finds_the_gap_in_a_wall() {
+        let mut game = walled_in();
+        assert_eq!(min_cut(&game, (4, 4)), vec![(4, 2)]);
+        assert_eq!(search_blocker_move(&game, 1), Some((4, 2)));
+
+        game.blocker.blocked_squares.insert((4, 2));
+        assert!(min_cut(&game, (4, 4)).is_empty());
+    }
+
+    #[test]
+    fn min_cut_on_an_open_board_surrounds_the_runner() {
+        let game = Game::new(9, 1, Goal::AnyEdge);
+        let cut = min_cut(&game, (4, 4));
+        assert_eq!(cut.len(), 8);
+        let mut blocked = game.clone();
+        blocked.blocker.blocked_squares.extend(cut);
+        assert!(!blocked.goal_reachable());
+    }
+}
@@ -0,0 +1,266 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use serde::Serialize;
+use angel::agent::{Agent, CpuAgent};
+use angel::ai::Difficulty;
+use angel::setup::{self, Pattern, Setup};
+use angel::{Game, GameState, Goal, MovementRule, Role, MAX_POWER};
+
+const USAGE: &str = "usage: arena [--games N] [--runner easy|medium|hard] [--blocker easy|medium|hard] \
+[--sizes S1,S2,..] [--powers P1,P2,..] [--movement RULE] [--blocks COUNT | --density FRACTION] [--pattern uniform|symmetric|clustered|empty] [--unbounded] [--max-turns N] [--threads N] [--seed SEED] [--records DIR] [--end-when-sealed]";
+
+struct Args {
+    games: usize,
+    runner: Difficulty,
+    blocker: Difficulty,
+    sizes: Vec<i16>,
+    powers: Vec<i16>,
+    blocks: usize,
+    /// Overrides `blocks` with a share of each board size.
+    density: Option<f64>,
+    pattern: Pattern,
+    rule: MovementRule,
+    goal: Goal,
+    end_when_sealed: bool,
+    max_turns: i32,
+    threads: usize,
+    seed: u64,
+    records: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct Record {
+    seed: u64,
+    runner: Difficulty,
+    blocker: Difficulty,
+    game: Game,
+}
+
+struct Outcome {
+    runner_won: bool,
+    turn_count: i32,
+}
+
+fn play_game(args: &Args, squares: i16, power: i16, seed: u64) -> Game {
+    let mut game = Game::new(squares, power, args.goal);
+    game.end_when_sealed = args.end_when_sealed;
+    game.rule = args.rule;
+    // Surviving that long counts as an escape.
+    game.turn_limit = Some(args.max_turns);
+    let blocks = args.density.map_or(args.blocks, |density| setup::blocks_for_density(squares, density));
+    Setup {
+        pattern: args.pattern,
+        blocks,
+        margin: 1,
+        seed,
+    }
+    .apply(&mut game);
+    if !game.is_over() {
+        game.game_state = GameState::Playing;
+    }
+
+    let mut runner = CpuAgent::seeded(args.runner, seed);
+    let mut blocker = CpuAgent::seeded(args.blocker, seed.wrapping_add(1));
+    while !game.is_over() {
+        let role = game.current_player;
+        let agent: &mut dyn Agent = match role {
+            Role::Runner => &mut runner,
+            Role::Blocker => &mut blocker,
+        };
+        let played = agent.choose_move(&game).map(|pos| game.play(role, pos));
+        if !matches!(played, Some(Ok(()))) {
+            // No legal move, or an illegal one: that side loses.
+            let _ = game.resign(role);
+        }
+    }
+    game
+}
+
+fn run(args: &Args, squares: i16, power: i16) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::with_capacity(args.games));
+
+    thread::scope(|scope| {
+        for _ in 0..args.threads {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= args.games {
+                    break;
+                }
+                let seed = args.seed.wrapping_add(i as u64);
+                let game = play_game(args, squares, power, seed);
+                if let Some(dir) = &args.records {
+                    let path = dir.join(format!("{}x{}-p{}-{}.json", squares, squares, power, i));
+                    let record = Record {
+                        seed,
+                        runner: args.runner,
+                        blocker: args.blocker,
+                        game: game.clone(),
+                    };
+                    let written = serde_json::to_vec_pretty(&record)
+                        .map_err(|e| e.to_string())
+                        .and_then(|json| fs::write(&path, json).map_err(|e| e.to_string()));
+                    if let Err(e) = written {
+                        eprintln!("Could not write {}: {}", path.display(), e);
+                    }
+                }
+                outcomes.lock().unwrap().push(Outcome {
+                    runner_won: game.won,
+                    turn_count: game.turn_count,
+                });
+            });
+        }
+    });
+
+    outcomes.into_inner().unwrap()
+}
+
+/// 95% Wilson score interval for `wins` out of `n`.
+fn wilson_interval(wins: usize, n: usize) -> (f64, f64) {
+    let z = 1.96;
+    let n = n as f64;
+    let p = wins as f64 / n;
+    let center = (p + z * z / (2. * n)) / (1. + z * z / n);
+    let margin = z / (1. + z * z / n) * (p * (1. - p) / n + z * z / (4. * n * n)).sqrt();
+    ((center - margin).max(0.), (center + margin).min(1.))
+}
+
+fn report(args: &Args, squares: i16, power: i16, outcomes: &[Outcome]) {
+    let n = outcomes.len();
+    let wins = outcomes.iter().filter(|outcome| outcome.runner_won).count();
+    let (low, high) = wilson_interval(wins, n);
+
+    let turns: Vec<f64> = outcomes.iter().map(|outcome| outcome.turn_count as f64).collect();
+    let mean = turns.iter().sum::<f64>() / n as f64;
+    let variance = if n > 1 {
+        turns.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / (n - 1) as f64
+    } else {
+        0.
+    };
+    let margin = 1.96 * (variance / n as f64).sqrt();
+
+    let board = match args.goal {
+        Goal::Survive => format!("unbounded ({}x{} area)", squares, squares),
+        _ => format!("{}x{}", squares, squares),
+    };
+    println!(
+        "{} power {} ({}), {} runner vs {} blocker: runner won {}/{} ({:.1}%, 95% CI {:.1}%-{:.1}%), avg {:.2} turns (95% CI ±{:.2})",
+        board,
+        power,
+        args.rule,
+        args.runner,
+        args.blocker,
+        wins,
+        n,
+        100. * wins as f64 / n as f64,
+        100. * low,
+        100. * high,
+        mean,
+        margin
+    );
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {:?} for {}", value, flag))
+}
+
+fn parse_list<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<Vec<T>, String> {
+    let value: String = parse_value(flag, value)?;
+    value
+        .split(',')
+        .map(|item| parse_value(flag, Some(item.trim().to_string())))
+        .collect()
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        games: 100,
+        runner: Difficulty::Easy,
+        blocker: Difficulty::Easy,
+        sizes: vec![21],
+        powers: vec![1],
+        blocks: 42,
+        density: None,
+        pattern: Pattern::Uniform,
+        rule: MovementRule::Chebyshev,
+        goal: Goal::AnyEdge,
+        end_when_sealed: false,
+        max_turns: 1000,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        seed: 0,
+        records: None,
+    };
+
+    let mut argv = std::env::args().skip(1);
+    while let Some(flag) = argv.next() {
+        match flag.as_str() {
+            "--games" => args.games = parse_value(&flag, argv.next())?,
+            "--runner" => args.runner = parse_value(&flag, argv.next())?,
+            "--blocker" => args.blocker = parse_value(&flag, argv.next())?,
+            "--sizes" => args.sizes = parse_list(&flag, argv.next())?,
+            "--powers" => args.powers = parse_list(&flag, argv.next())?,
+            "--blocks" => args.blocks = parse_value(&flag, argv.next())?,
+            "--density" => args.density = Some(parse_value(&flag, argv.next())?),
+            "--pattern" => args.pattern = parse_value(&flag, argv.next())?,
+            "--max-turns" => args.max_turns = parse_value(&flag, argv.next())?,
+            "--threads" => args.threads = parse_value(&flag, argv.next())?,
+            "--seed" => args.seed = parse_value(&flag, argv.next())?,
+            "--movement" => args.rule = parse_value(&flag, argv.next())?,
+            "--unbounded" => args.goal = Goal::Survive,
+            "--end-when-sealed" => args.end_when_sealed = true,
+            "--records" => args.records = Some(parse_value(&flag, argv.next())?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(format!("unknown argument {:?}", flag)),
+        }
+    }
+
+    if args.max_turns < 1 {
+        return Err(String::from("--max-turns must be at least 1"));
+    }
+    if args.games == 0 {
+        return Err(String::from("--games must be at least 1"));
+    }
+    if args.sizes.iter().any(|&size| size < 3) {
+        return Err(String::from("--sizes must all be at least 3"));
+    }
+    if args.powers.iter().any(|power| !(1..=MAX_POWER).contains(power)) {
+        return Err(format!("--powers must all be between 1 and {}", MAX_POWER));
+    }
+    if args.density.is_some_and(|density| !(0. ..=1.).contains(&density)) {
+        return Err(String::from("--density must be between 0 and 1"));
+    }
+    args.threads = args.threads.max(1);
+    Ok(args)
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Some(dir) = &args.records {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("Could not create {}: {}", dir.display(), e);
+            std::process::exit(1);
+        }
+    }
+
+    for &squares in &args.sizes {
+        for &power in &args.powers {
+            let outcomes = run(&args, squares, power);
+            report(&args, squares, power, &outcomes);
+        }
+    }
+}
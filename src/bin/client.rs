@@ -1,141 +1,200 @@
 use macroquad::prelude::*;
-use std::net::TcpStream;
-use std::io::{Read, Write};
-use serde::{Serialize, Deserialize};
-
-const SQUARES: i16 = 21;
-const POWER: i16 = 1;
-
-type Point = (i16, i16);
-
-#[derive(Serialize, Deserialize, Clone)]
-enum GameState {
-    WaitingForPlayers,
-    Playing,
-    GameOver(bool),
-    CPUMode,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-struct Runner {
-    position: (i16, i16),
-    power: i16,
-    moved: bool,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-struct Blocker {
-    blocked_squares: Vec<(i16, i16)>,
-    moved: bool,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-struct Game {
-    runner: Runner,
-    blocker: Blocker,
-    game_over: bool,
-    won: bool,
-    turn_count: i32,
-    game_state: GameState,
-    squares: i16,
-    power: i16,
-    current_player: String,
+use std::io;
+use std::net::{Shutdown, TcpStream};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use angel::ai::Difficulty;
+use angel::save::SavedGame;
+use angel::protocol::{ClientMessage, ServerMessage, HEARTBEAT_INTERVAL, PROTOCOL_VERSION};
+use angel::{net, Game, GameState, Goal, MovementRule, Point, Role};
+
+const SAVE_PATH: &str = "angel-save.json";
+const USAGE: &str = "usage: client [--server ADDR:PORT] [--room NAME] [--role runner|blocker] [--power POWER] [--movement RULE]";
+
+#[derive(Clone)]
+struct Args {
+    server: String,
+    room: String,
+    role: Option<Role>,
+    /// Only used if joining creates the room.
+    power: Option<i16>,
+    rule: Option<MovementRule>,
 }
 
-fn send_action(action: &str) -> (Game, String, bool) {
-    let mut stream = TcpStream::connect("127.0.0.1:25567").unwrap();
-    stream.write_all(action.as_bytes()).unwrap();
+fn parse_args() -> Result<Args, String> {
+    let mut parsed = Args {
+        server: String::from("127.0.0.1:25567"),
+        room: String::from("default"),
+        role: None,
+        power: None,
+        rule: None,
+    };
 
-    let mut buffer = [0; 4096];
-    let n = stream.read(&mut buffer).unwrap();
-    let response = String::from_utf8_lossy(&buffer[..n]);
-    serde_json::from_str(&response).unwrap()
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next();
+        match (flag.as_str(), value) {
+            ("-h" | "--help", _) => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            ("--server", Some(value)) => parsed.server = value,
+            ("--room", Some(value)) => parsed.room = value,
+            ("--role", Some(value)) => parsed.role = Some(value.parse()?),
+            ("--power", Some(value)) => {
+                parsed.power = Some(value.parse().map_err(|_| format!("invalid value {:?} for --power", value))?)
+            }
+            ("--movement", Some(value)) => parsed.rule = Some(value.parse()?),
+            ("--server" | "--room" | "--role" | "--power" | "--movement", None) => return Err(format!("missing value for {}", flag)),
+            _ => return Err(format!("unknown argument {:?}", flag)),
+        }
+    }
+    Ok(parsed)
 }
 
 struct GameClient {
-    stream: Option<TcpStream>,
+    stream: TcpStream,
+    role: Role,
+    token: String,
+    incoming: Receiver<ServerMessage>,
+    args: Args,
     max_retries: u32,
+    last_sent: Instant,
+    notice: Option<String>,
+    /// Who asked to take back a move, while the request is open.
+    takeback: Option<Role>,
+}
+
+fn handshake(server: &str, message: &ClientMessage) -> Result<(TcpStream, Role, String, Game), io::Error> {
+    let mut stream = TcpStream::connect(server)?;
+    net::write_frame(&mut stream, message)?;
+    match net::read_frame(&mut stream)? {
+        ServerMessage::Welcome { role, token, game, .. } => Ok((stream, role, token, game)),
+        ServerMessage::Refused { reason } => Err(io::Error::other(reason)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "expected Welcome")),
+    }
 }
 
 impl GameClient {
-    fn new(max_retries: u32) -> Self {
-        Self {
-            stream: None,
-            max_retries,
+    fn connect(args: Args, mut token: Option<String>, max_retries: u32) -> Result<(Self, Game), io::Error> {
+        let mut retries = 0;
+        loop {
+            let message = match &token {
+                Some(token) => ClientMessage::Resume {
+                    version: PROTOCOL_VERSION,
+                    token: token.clone(),
+                },
+                None => ClientMessage::Join {
+                    version: PROTOCOL_VERSION,
+                    room: args.room.clone(),
+                    role: args.role,
+                    power: args.power,
+                    rule: args.rule,
+                },
+            };
+            match handshake(&args.server, &message) {
+                Ok((stream, role, token, game)) => {
+                    let mut reader = stream.try_clone()?;
+                    let (sender, incoming) = mpsc::channel();
+                    thread::spawn(move || {
+                        while let Ok(message) = net::read_frame(&mut reader) {
+                            if sender.send(message).is_err() {
+                                break;
+                            }
+                        }
+                    });
+
+                    let client = GameClient {
+                        stream,
+                        role,
+                        token,
+                        incoming,
+                        args,
+                        max_retries,
+                        last_sent: Instant::now(),
+                        notice: None,
+                        takeback: None,
+                    };
+                    return Ok((client, game));
+                }
+                // The seat was forfeited in the meantime; take a fresh one.
+                Err(e) if token.is_some() && e.kind() == io::ErrorKind::Other => token = None,
+                Err(e) => {
+                    retries += 1;
+                    if retries >= max_retries {
+                        return Err(e);
+                    }
+                    thread::sleep(Duration::from_millis(500));
+                }
+            }
         }
     }
 
-    fn connect(&mut self) -> Result<(), std::io::Error> {
-        self.stream = Some(TcpStream::connect("127.0.0.1:25567")?);
-        Ok(())
+    fn send(&mut self, message: &ClientMessage) -> Result<(), io::Error> {
+        let result = net::write_frame(&mut self.stream, message);
+        self.last_sent = Instant::now();
+        if result.is_err() {
+            let _ = self.stream.shutdown(Shutdown::Both);
+        }
+        result
     }
 
-    fn ensure_connected(&mut self) -> Result<(), std::io::Error> {
-        if self.stream.is_none() {
-            self.connect()?;
+    fn sync(&mut self, game: &mut Game) -> Result<(), io::Error> {
+        if self.last_sent.elapsed() >= HEARTBEAT_INTERVAL {
+            let _ = self.send(&ClientMessage::Heartbeat);
         }
-        Ok(())
-    }
 
-    fn send_action(&mut self, action: &str) -> Result<(Game, String, bool), std::io::Error> {
-        let mut retries = 0;
         loop {
-            if retries >= self.max_retries {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Max retries reached"
-                ));
+            match self.incoming.try_recv() {
+                Ok(message) => self.apply_response(game, message),
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => {
+                    let args = Args {
+                        role: Some(self.role),
+                        ..self.args.clone()
+                    };
+                    let (client, new_game) = GameClient::connect(args, Some(self.token.clone()), self.max_retries)?;
+                    *self = client;
+                    *game = new_game;
+                }
             }
+        }
+    }
 
-            match self.ensure_connected() {
-                Ok(()) => {
-                    let stream = self.stream.as_mut().unwrap();
-                    match stream.write_all(action.as_bytes()) {
-                        Ok(()) => {
-                            let mut buffer = [0; 4096];
-                            match stream.read(&mut buffer) {
-                                Ok(n) if n > 0 => {
-                                    let response = String::from_utf8_lossy(&buffer[..n]);
-                                    return Ok(serde_json::from_str(&response).unwrap());
-                                }
-                                Ok(_) | Err(_) => {
-                                    self.stream = None;
-                                    retries += 1;
-                                    std::thread::sleep(std::time::Duration::from_millis(500));
-                                    continue;
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            self.stream = None;
-                            retries += 1;
-                            std::thread::sleep(std::time::Duration::from_millis(500));
-                            continue;
-                        }
-                    }
-                }
-                Err(_) => {
-                    retries += 1;
-                    std::thread::sleep(std::time::Duration::from_millis(500));
-                    continue;
+    fn apply_response(&mut self, game: &mut Game, response: ServerMessage) {
+        match response {
+            ServerMessage::Welcome { role, token, game: new_game, .. } => {
+                self.role = role;
+                self.token = token;
+                *game = new_game;
+            }
+            ServerMessage::StateUpdate { game: new_game } => *game = new_game,
+            ServerMessage::Error { error } => println!("Move rejected: {}", error),
+            ServerMessage::Refused { reason } => println!("Refused by server: {}", reason),
+            ServerMessage::RoomList { .. } => {}
+            ServerMessage::PlayerLeft { role, grace_secs } => {
+                self.notice = Some(format!("The {} disconnected and has {} s to come back.", role, grace_secs));
+            }
+            ServerMessage::PlayerReturned { .. } => self.notice = None,
+            ServerMessage::TakebackRequested { role } => self.takeback = Some(role),
+            ServerMessage::TakebackAnswered { accepted } => {
+                if self.takeback == Some(self.role) {
+                    println!("Takeback {}", if accepted { "accepted" } else { "declined" });
                 }
+                self.takeback = None;
             }
         }
     }
 }
 
-fn is_within_power(start: Point, end: Point, power: i16) -> bool {
-    let dx = (start.0 - end.0).abs();
-    let dy = (start.1 - end.1).abs();
-    dx <= power && dy <= power && !(dx == 0 && dy == 0)
-}
-
-fn get_grid_pos(mouse_pos: Vec2, offset_x: f32, offset_y: f32, sq_size: f32, sent_sq: i16) -> Option<Point> {
+fn get_grid_pos(mouse_pos: Vec2, offset_x: f32, offset_y: f32, sq_size: f32, sent_sq: i16, origin: Point) -> Option<Point> {
     let grid_x = ((mouse_pos.x - offset_x) / sq_size) as i16;
     let grid_y = ((mouse_pos.y - offset_y) / sq_size) as i16;
 
     if grid_x >= 0 && grid_x < sent_sq && grid_y >= 0 && grid_y < sent_sq {
-        Some((grid_x, grid_y))
+        Some((origin.0 + grid_x, origin.1 + grid_y))
     } else {
         None
     }
@@ -143,27 +202,52 @@ fn get_grid_pos(mouse_pos: Vec2, offset_x: f32, offset_y: f32, sq_size: f32, sen
 
 #[macroquad::main("Angel Problem - Multiplayer")]
 async fn main() {
-    let mut client = GameClient::new(3);
-    let (mut game, mut player_type, _) = match client.send_action("init") {
-        Ok(response) => response,
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{}\n{}", e, USAGE);
+            return;
+        }
+    };
+    let (mut client, mut game) = match GameClient::connect(args, None, 3) {
+        Ok(connection) => connection,
         Err(e) => {
             println!("Failed to connect to server: {}", e);
             return;
         }
     };
-    let mut last_update = get_time();
 
+    let mut difficulty = Difficulty::default();
+    // Resigning takes a second press of [Q], so it cannot happen by accident.
+    let mut confirm_resign = false;
     loop {
+        if let Err(e) = client.sync(&mut game) {
+            println!("Lost connection to server: {}", e);
+            break;
+        }
+        let player_type = client.role;
         clear_background(LIGHTGRAY);
-        if matches!(game.game_state, GameState::WaitingForPlayers) && is_key_pressed(KeyCode::Space) {
-            match client.send_action("activate_cpu") {
-                Ok((new_game, new_player_type, _)) => {
-                    game = new_game;
-                    player_type = new_player_type;
-                }
-                Err(e) => {
+        if matches!(game.game_state, GameState::WaitingForPlayers) {
+            if is_key_pressed(KeyCode::Key1) {
+                difficulty = Difficulty::Easy;
+            } else if is_key_pressed(KeyCode::Key2) {
+                difficulty = Difficulty::Medium;
+            } else if is_key_pressed(KeyCode::Key3) {
+                difficulty = Difficulty::Hard;
+            }
+            let cpu_role = if is_key_pressed(KeyCode::Space) {
+                Some(player_type)
+            } else if is_key_pressed(KeyCode::R) {
+                Some(Role::Runner)
+            } else if is_key_pressed(KeyCode::B) {
+                Some(Role::Blocker)
+            } else {
+                None
+            };
+            if let Some(role) = cpu_role {
+                if let Err(e) = client.send(&ClientMessage::RequestCpu { role, difficulty }) {
                     draw_text(
-                        &format!("CPU Failed {}", e),
+                        format!("CPU Failed {}", e).as_str(),
                         10.,
                         30.,
                         20.,
@@ -185,12 +269,39 @@ async fn main() {
                     font_size,
                     DARKGRAY,
                 );
+
+                let text = format!(
+                    "You are the {}. Press [R] to run or [B] to block against the CPU instead.",
+                    player_type
+                );
+                let text_size = measure_text(&text, None, 20, 1.0);
+                draw_text(
+                    &text,
+                    screen_width() / 2. - text_size.width / 2.,
+                    screen_height() / 2. + 40.,
+                    20.,
+                    DARKGRAY,
+                );
+
+                let text = format!("CPU difficulty: {} (press [1] easy, [2] medium, [3] hard)", difficulty);
+                let text_size = measure_text(&text, None, 20, 1.0);
+                draw_text(
+                    &text,
+                    screen_width() / 2. - text_size.width / 2.,
+                    screen_height() / 2. + 70.,
+                    20.,
+                    DARKGRAY,
+                );
             }
             GameState::CPUMode | GameState::Playing => {
                 let game_size = screen_width().min(screen_height());
                 let offset_x = (screen_width() - game_size) / 2. + 10.;
                 let offset_y = (screen_height() - game_size) / 2. + 10.;
                 let sq_size = (screen_height() - offset_y * 2.) / game.squares as f32;
+                // On an unbounded board the view follows the angel.
+                let origin = game.origin();
+                let board_x = offset_x - origin.0 as f32 * sq_size;
+                let board_y = offset_y - origin.1 as f32 * sq_size;
 
                 draw_rectangle(offset_x, offset_y, game_size - 20., game_size - 20., WHITE);
                 for i in 1..game.squares {
@@ -212,22 +323,20 @@ async fn main() {
                     );
                 }
 
-                for i in 0..game.squares {
-                    for (x, y) in [(i, 0), (i, game.squares-1), (0, i), (game.squares-1, i)] {
-                        draw_rectangle(
-                            offset_x + x as f32 * sq_size,
-                            offset_y + y as f32 * sq_size,
-                            sq_size,
-                            sq_size,
-                            SKYBLUE,
-                        );
-                    }
+                for (x, y) in game.area().filter(|pos| game.is_goal(*pos)) {
+                    draw_rectangle(
+                        board_x + x as f32 * sq_size,
+                        board_y + y as f32 * sq_size,
+                        sq_size,
+                        sq_size,
+                        SKYBLUE,
+                    );
                 }
 
-                for pos in &game.blocker.blocked_squares {
+                for pos in game.blocker.blocked_squares.iter().filter(|pos| game.in_area(**pos)) {
                     draw_rectangle(
-                        offset_x + pos.0 as f32 * sq_size,
-                        offset_y + pos.1 as f32 * sq_size,
+                        board_x + pos.0 as f32 * sq_size,
+                        board_y + pos.1 as f32 * sq_size,
                         sq_size,
                         sq_size,
                         RED,
@@ -235,36 +344,45 @@ async fn main() {
                 }
 
                 draw_rectangle(
-                    offset_x + game.runner.position.0 as f32 * sq_size,
-                    offset_y + game.runner.position.1 as f32 * sq_size,
+                    board_x + game.runner.position.0 as f32 * sq_size,
+                    board_y + game.runner.position.1 as f32 * sq_size,
                     sq_size,
                     sq_size,
                     GOLD,
                 );
 
                 let mouse_pos = mouse_position();
-                let hover_pos = get_grid_pos(Vec2::new(mouse_pos.0, mouse_pos.1), offset_x, offset_y, sq_size, game.squares);
+                let hover_pos = get_grid_pos(Vec2::new(mouse_pos.0, mouse_pos.1), offset_x, offset_y, sq_size, game.squares, origin);
 
                 if let Some(pos) = hover_pos {
                     if game.current_player == player_type {
-                        let (color, valid_move) = match player_type.as_str() {
-                            "runner" => (
+                        if player_type == Role::Runner {
+                            // Everywhere the angel can land this turn.
+                            for (x, y) in game.runner_moves_in_area(game.runner.position) {
+                                draw_rectangle(
+                                    board_x + x as f32 * sq_size,
+                                    board_y + y as f32 * sq_size,
+                                    sq_size,
+                                    sq_size,
+                                    Color::new(0.0, 1.0, 0.0, 0.12),
+                                );
+                            }
+                        }
+                        let (color, valid_move) = match player_type {
+                            Role::Runner => (
                                 Color::new(0.0, 1.0, 0.0, 0.3),
-                                is_within_power(game.runner.position, pos, game.power)
-                                    && !game.blocker.blocked_squares.contains(&pos)
+                                game.is_valid_runner_move(pos)
                             ),
-                            "blocker" => (
+                            Role::Blocker => (
                                 Color::new(1.0, 0.0, 0.0, 0.3),
-                                pos != game.runner.position
-                                    && !game.blocker.blocked_squares.contains(&pos)
+                                game.is_valid_block(pos)
                             ),
-                            _ => (Color::new(0.0, 0.0, 0.0, 0.0), false),
                         };
 
                         if valid_move {
                             draw_rectangle(
-                                offset_x + pos.0 as f32 * sq_size,
-                                offset_y + pos.1 as f32 * sq_size,
+                                board_x + pos.0 as f32 * sq_size,
+                                board_y + pos.1 as f32 * sq_size,
                                 sq_size,
                                 sq_size,
                                 color,
@@ -275,56 +393,74 @@ async fn main() {
 
                 if is_mouse_button_pressed(MouseButton::Left) && game.current_player == player_type {
                     if let Some(grid_pos) = hover_pos {
-                        let action = match player_type.as_str() {
-                            "runner" if is_within_power(game.runner.position, grid_pos, game.power)
-                                && !game.blocker.blocked_squares.contains(&grid_pos) => {
-                                Some(format!("move_runner {} {}", grid_pos.0, grid_pos.1))
-                            }
-                            "blocker" if grid_pos != game.runner.position
-                                && !game.blocker.blocked_squares.contains(&grid_pos) => {
-                                Some(format!("move_blocker {} {}", grid_pos.0, grid_pos.1))
-                            }
-                            _ => None,
+                        let valid_move = match player_type {
+                            Role::Runner => game.is_valid_runner_move(grid_pos),
+                            Role::Blocker => game.is_valid_block(grid_pos),
                         };
 
-                        if let Some(action) = action {
-                            let (new_game, new_player_type, _) = match client.send_action(&action){
-                                Ok(response) => response,
-                                Err(e) => {
-                                    println!("Failed to send action: {}", e);
-                                    break;
-                                }
-                            };
-                            game = new_game;
-                            player_type = new_player_type;
+                        if valid_move {
+                            let message = ClientMessage::Move { x: grid_pos.0, y: grid_pos.1 };
+                            if let Err(e) = client.send(&message) {
+                                println!("Failed to send action: {}", e);
+                            }
                         }
                     }
                 }
 
+                if is_key_pressed(KeyCode::Q) {
+                    if confirm_resign {
+                        if let Err(e) = client.send(&ClientMessage::Resign) {
+                            println!("Failed to resign: {}", e);
+                        }
+                    }
+                    confirm_resign = !confirm_resign;
+                } else if is_key_pressed(KeyCode::Escape) {
+                    confirm_resign = false;
+                }
+
+                let turn = match game.turn_limit {
+                    Some(limit) => format!("{}/{}", game.turn_count, limit),
+                    None => game.turn_count.to_string(),
+                };
                 draw_text(
-                    &format!("Turn: {} | You are: {}", game.turn_count, player_type),
+                    format!("Turn: {} | Power: {} ({}) | You are: {}", turn, game.power, game.rule, player_type).as_str(),
                     10.,
                     30.,
                     20.,
                     DARKGRAY,
                 );
                 draw_text(
-                    &format!("Current turn: {}", game.current_player),
+                    format!("Current turn: {}", game.current_player).as_str(),
                     10.,
                     60.,
                     20.,
                     if game.current_player == player_type { GREEN } else { DARKGRAY },
                 );
+                if confirm_resign {
+                    draw_text("Press [Q] again to resign, [Esc] to keep playing.", 10., 90., 20., RED);
+                } else {
+                    draw_text("Press [Q] to resign, [T] to take back your last move.", 10., 90., 20., DARKGRAY);
+                }
+                if let Some(notice) = &client.notice {
+                    draw_text(notice, 10., 120., 20., RED);
+                }
             }
             GameState::GameOver(runner_won) => {
-                let text = if runner_won {
-                    if player_type == "runner" {
+                confirm_resign = false;
+                let text = if runner_won && game.goal == Goal::Survive {
+                    if player_type == Role::Runner {
+                        "You won! The runner survived!"
+                    } else {
+                        "You lost! The runner survived!"
+                    }
+                } else if runner_won {
+                    if player_type == Role::Runner {
                         "You won! The runner escaped!"
                     } else {
                         "You lost! The runner escaped!"
                     }
                 } else {
-                    if player_type == "blocker" {
+                    if player_type == Role::Blocker {
                         "You won! The runner is trapped!"
                     } else {
                         "You lost! You are trapped!"
@@ -338,23 +474,91 @@ async fn main() {
                     screen_width() / 2. - text_size.width / 2.,
                     screen_height() / 2.,
                     font_size,
-                    if runner_won == (player_type == "runner") { GREEN } else { RED },
+                    if runner_won == (player_type == Role::Runner) { GREEN } else { RED },
                 );
+
+                let text = "Press [enter] to play again.";
+                let text_size = measure_text(text, None, 20, 1.0);
+                draw_text(
+                    text,
+                    screen_width() / 2. - text_size.width / 2.,
+                    screen_height() / 2. + 40.,
+                    20.,
+                    DARKGRAY,
+                );
+                if is_key_pressed(KeyCode::Enter) {
+                    if let Err(e) = client.send(&ClientMessage::NewGame) {
+                        println!("Failed to start a new game: {}", e);
+                    }
+                }
             }
         }
-        if game.current_player != player_type && get_time() - last_update >= 0.1 {
-            let (new_game, new_player_type, _) = match client.send_action("poll") {
-                Ok(response) => response,
-                Err(e) => {
-                    println!("Failed to poll for updates: {}", e);
-                    break;
+
+        if !matches!(game.game_state, GameState::WaitingForPlayers) {
+            match client.takeback {
+                Some(role) if role == player_type => {
+                    draw_text(
+                        format!("Waiting for the {} to allow your takeback...", role.opponent()).as_str(),
+                        10.,
+                        screen_height() - 35.,
+                        20.,
+                        DARKGRAY,
+                    );
                 }
-            };
-            game = new_game;
-            player_type = new_player_type;
-            last_update = get_time();
+                Some(role) => {
+                    draw_text(
+                        format!("The {} asks to take back their last move: [A] accept, [D] decline", role).as_str(),
+                        10.,
+                        screen_height() - 35.,
+                        20.,
+                        RED,
+                    );
+                    let answer = if is_key_pressed(KeyCode::A) {
+                        Some(true)
+                    } else if is_key_pressed(KeyCode::D) {
+                        Some(false)
+                    } else {
+                        None
+                    };
+                    if let Some(accept) = answer {
+                        if let Err(e) = client.send(&ClientMessage::AnswerTakeback { accept }) {
+                            println!("Failed to answer the takeback: {}", e);
+                        }
+                    }
+                }
+                None => {
+                    if is_key_pressed(KeyCode::T) {
+                        if let Err(e) = client.send(&ClientMessage::RequestTakeback) {
+                            println!("Failed to request a takeback: {}", e);
+                        }
+                    }
+                }
+            }
         }
 
+        if is_key_pressed(KeyCode::S) {
+            match SavedGame::from_game(&game).write(Path::new(SAVE_PATH)) {
+                Ok(()) => println!("Saved the game to {}", SAVE_PATH),
+                Err(e) => println!("Failed to save the game: {}", e),
+            }
+        }
+        if is_key_pressed(KeyCode::L) {
+            match SavedGame::read(Path::new(SAVE_PATH)) {
+                Ok(save) => {
+                    if let Err(e) = client.send(&ClientMessage::Load { save }) {
+                        println!("Failed to load the game: {}", e);
+                    }
+                }
+                Err(e) => println!("Failed to read {}: {}", SAVE_PATH, e),
+            }
+        }
+        draw_text(
+            "[S] save the game, [L] load the last save",
+            10.,
+            screen_height() - 10.,
+            16.,
+            DARKGRAY,
+        );
         next_frame().await;
     }
 }
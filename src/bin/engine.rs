@@ -0,0 +1,107 @@
+use std::io::{self, BufRead, Write};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use angel::ai::{self, Difficulty};
+use angel::engine::{format_point, parse_goal, parse_point};
+use angel::{Game, Goal, MovementRule, Role, MAX_POWER};
+
+const USAGE: &str = "usage: engine [--difficulty easy|medium|hard] [--seed SEED]";
+
+fn parse_args() -> Result<(Difficulty, StdRng), String> {
+    let mut difficulty = Difficulty::Hard;
+    let mut rng = StdRng::from_entropy();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", flag));
+        match flag.as_str() {
+            "--difficulty" => difficulty = value()?.parse()?,
+            "--seed" => {
+                let seed = value()?;
+                let seed = seed.parse().map_err(|_| format!("invalid value {:?} for --seed", seed))?;
+                rng = StdRng::seed_from_u64(seed);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(format!("unknown argument {:?}", flag)),
+        }
+    }
+    Ok((difficulty, rng))
+}
+
+/// Reads `position runner <x,y> blocks <x,y> ...` into `game`.
+fn set_position(game: &mut Game, args: &[&str]) -> Option<()> {
+    let ["runner", runner, "blocks", blocks @ ..] = args else {
+        return None;
+    };
+    game.runner.position = parse_point(runner)?;
+    game.blocker.blocked_squares = blocks.iter().map(|pos| parse_point(pos)).collect::<Option<_>>()?;
+    Some(())
+}
+
+fn main() {
+    let (difficulty, mut rng) = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let (mut squares, mut power, mut goal) = (21, 1, Goal::AnyEdge);
+    let mut rule = MovementRule::Chebyshev;
+    let mut game = Game::new(squares, power, goal);
+    let mut out = io::stdout().lock();
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let reply = match words.as_slice() {
+            ["angel"] => format!("id name angel {}\nangelok", difficulty),
+            ["isready"] => String::from("readyok"),
+            ["setoption", "name", "size", "value", value] => {
+                squares = value.parse().unwrap_or(squares);
+                continue;
+            }
+            ["setoption", "name", "power", "value", value] => {
+                power = value.parse().map_or(power, |value: i16| value.clamp(1, MAX_POWER));
+                continue;
+            }
+            ["setoption", "name", "goal", "value", value] => {
+                goal = parse_goal(value).unwrap_or(goal);
+                continue;
+            }
+            ["setoption", "name", "movement", "value", value] => {
+                rule = value.parse().unwrap_or(rule);
+                continue;
+            }
+            ["newgame"] => {
+                game = Game::new(squares, power, goal);
+                game.rule = rule;
+                continue;
+            }
+            ["position", args @ ..] => {
+                if set_position(&mut game, args).is_none() {
+                    eprintln!("engine: bad position {:?}", line);
+                }
+                continue;
+            }
+            ["go", role] => {
+                let Ok(role) = role.parse::<Role>() else { continue };
+                game.current_player = role;
+                let best = match role {
+                    Role::Runner => ai::runner_move(&game, difficulty, &mut rng),
+                    Role::Blocker => ai::blocker_move(&game, difficulty, &mut rng),
+                };
+                // With nothing legal left, any answer is as good as resigning.
+                format!("bestmove {}", format_point(best.unwrap_or(game.runner.position)))
+            }
+            ["quit"] => break,
+            _ => continue,
+        };
+        if writeln!(out, "{}", reply).and_then(|()| out.flush()).is_err() {
+            break;
+        }
+    }
+}
@@ -1,271 +1,820 @@
-use std::net::TcpListener;
-use std::io::{Read, Write};
-use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::path::PathBuf;
+use std::net::{Shutdown, TcpListener, TcpStream};
 use rand::Rng;
-use rand::seq::SliceRandom;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+use angel::agent::{self, Agent, CpuAgent, RemoteAgent};
+use angel::engine::EngineAgent;
+use angel::ai::{self, Difficulty};
+use angel::save::SavedGame;
+use angel::setup::{self, Pattern, Setup};
+use angel::protocol::{ClientMessage, RoomSummary, ServerMessage, HEARTBEAT_TIMEOUT, PROTOCOL_VERSION};
+use angel::{net, Game, GameError, GameState, Goal, MovementRule, Point, Role, MAX_POWER, MAX_SQUARES};
+
+const USAGE: &str = "usage: server [--bind ADDR] [--port PORT] [--size SQUARES] [--power POWER] [--movement chebyshev|manhattan|euclidean|knight|leaper:M,N|fool] [--unbounded] [--turn-limit TURNS] [--blocks COUNT | --density FRACTION] [--pattern uniform|symmetric|clustered|empty] [--seed SEED] [--engine COMMAND] [--resume FILE] [--end-when-sealed]";
+/// Where `--resume` puts the saved match; clients join it by default.
+const RESUME_ROOM: &str = "default";
+const GRACE_PERIOD: Duration = Duration::from_secs(30);
+/// A client that has not taken a frame off its socket for this long is
+/// dropped rather than left holding up its room.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+/// How many positions have been dealt; with `--seed`, each game takes the
+/// next seed so rooms and rematches do not all start the same.
+static GAMES_DEALT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone)]
+struct Config {
+    /// Board size, or on an unbounded board the area blocks start in.
+    squares: i16,
+    power: i16,
+    rule: MovementRule,
+    goal: Goal,
+    turn_limit: Option<i32>,
+    blocks: usize,
+    pattern: Pattern,
+    /// Seed of the first game dealt; each later one uses the next.
+    seed: Option<u64>,
+    end_when_sealed: bool,
+    /// Engine program to seat instead of the built-in CPU.
+    engine: Option<String>,
+}
 
-const SQUARES: i16 = 21;
-const POWER: i16 = 1;
+impl Config {
+    /// These settings with the rules `save` was played under, for a room
+    /// that carries on from it.
+    fn with_rules_of(&self, save: &SavedGame) -> Config {
+        Config {
+            squares: save.squares,
+            power: save.power,
+            rule: save.rule,
+            goal: save.goal,
+            turn_limit: save.turn_limit,
+            end_when_sealed: save.end_when_sealed,
+            ..self.clone()
+        }
+    }
 
-type Point = (i16, i16);
+    /// Whether `save` was played under these rules.
+    fn plays(&self, save: &SavedGame) -> bool {
+        (save.squares, save.power, save.rule, save.goal, save.turn_limit, save.end_when_sealed)
+            == (self.squares, self.power, self.rule, self.goal, self.turn_limit, self.end_when_sealed)
+    }
 
-#[derive(Serialize, Deserialize, Clone)]
-enum GameState {
-    WaitingForPlayers,
-    Playing,
-    GameOver(bool),
-    CPUMode,
+    fn describe(&self) -> String {
+        let board = match self.goal {
+            Goal::Survive => String::from("unbounded board"),
+            _ => format!("{}x{} board", self.squares, self.squares),
+        };
+        let goal = match self.goal {
+            Goal::TopRow => ", runner heads for the top row",
+            _ => "",
+        };
+        let limit = match self.turn_limit {
+            Some(limit) => format!(", runner wins after {} turns", limit),
+            None => String::new(),
+        };
+        let sealed = if self.end_when_sealed { ", ends when sealed" } else { "" };
+        format!("{}, power {}, {} moves{}{}{}", board, self.power, self.rule, goal, limit, sealed)
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct Runner {
-    position: Point,
-    power: i16,
-    moved: bool,
+struct Seat {
+    role: Role,
+    token: String,
+    /// `None` for a bot, which has no connection to lose.
+    connection: Option<u64>,
+    disconnected_at: Option<Instant>,
+    agent: Box<dyn Agent + Send>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct Blocker {
-    blocked_squares: Vec<Point>,
-    moved: bool,
+impl Seat {
+    fn remote(role: Role, connection: u64) -> Self {
+        Seat {
+            role,
+            token: format!("{:032x}", rand::thread_rng().gen::<u128>()),
+            connection: Some(connection),
+            disconnected_at: None,
+            agent: Box::new(RemoteAgent::default()),
+        }
+    }
+
+    fn bot(role: Role, agent: Box<dyn Agent + Send>) -> Self {
+        Seat {
+            role,
+            token: String::new(),
+            connection: None,
+            disconnected_at: None,
+            agent,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct Game {
-    runner: Runner,
-    blocker: Blocker,
-    game_over: bool,
-    won: bool,
-    turn_count: i32,
-    game_state: GameState,
-    squares: i16,
-    power: i16,
-    current_player: String,
+/// Fills a bot's seat while the bot thinks with the room unlocked. Bots
+/// always allow takebacks, so it answers for them meanwhile.
+struct Thinking;
+
+impl Agent for Thinking {
+    fn choose_move(&mut self, _game: &Game) -> Option<Point> {
+        None
+    }
+
+    fn accepts_takeback(&self) -> Option<bool> {
+        Some(true)
+    }
 }
 
-impl Game {
-    fn new() -> Self {
-        let mut rng = rand::thread_rng();
-        let mut blocked_squares = Vec::new();
-        for _ in 0..SQUARES * 2 {
-            blocked_squares.push((rng.gen_range(1..SQUARES-1), rng.gen_range(1..SQUARES-1)));
-        }
-
-        Game {
-            runner: Runner {
-                position: (SQUARES / 2, SQUARES / 2),
-                power: POWER,
-                moved: false,
-            },
-            blocker: Blocker {
-                blocked_squares,
-                moved: false,
-            },
-            game_over: false,
-            won: false,
-            turn_count: 0,
-            game_state: GameState::WaitingForPlayers,
-            squares: SQUARES,
-            power: POWER,
-            current_player: String::from("runner"),
-        }
-    }
-
-    fn update(&mut self, action: &str, player_type: &str) -> bool {
-        if player_type != self.current_player {
-            return false;
-        }
-
-        let action: Vec<&str> = action.trim().split_whitespace().collect();
-        match action[0] {
-            "move_runner" => {
-                if player_type == "runner" && !self.runner.moved && action.len() == 3 {
-                    let x: i16 = action[1].parse().unwrap();
-                    let y: i16 = action[2].parse().unwrap();
-                    self.runner.position = (x, y);
-                    self.runner.moved = true;
-                    self.current_player = String::from("blocker");
-
-                    if x == 0 || x == SQUARES - 1 || y == 0 || y == SQUARES - 1 {
-                        self.won = true;
-                        self.game_state = GameState::GameOver(true);
-                    }
-                }
+struct Room {
+    name: String,
+    config: Config,
+    game: Game,
+    seats: Vec<Seat>,
+    /// The player waiting for their opponent to allow a takeback.
+    takeback: Option<Role>,
+    active_connections: Vec<(u64, TcpStream)>,
+    /// Whether a bot is working out a move on its own thread.
+    bot_thinking: bool,
+    /// Bumped whenever a new position is put on the board, so a bot can
+    /// tell its move is for a game that has since been replaced.
+    epoch: u64,
+}
+
+type Lobby = Arc<Mutex<HashMap<String, Arc<Mutex<Room>>>>>;
+
+impl Room {
+    fn new(name: &str, config: &Config) -> Self {
+        Room {
+            name: name.to_string(),
+            config: config.clone(),
+            game: new_game(config),
+            seats: Vec::new(),
+            takeback: None,
+            active_connections: Vec::new(),
+            bot_thinking: false,
+            epoch: 0,
+        }
+    }
+
+    fn broadcast(&mut self, message: &ServerMessage) {
+        self.active_connections.retain_mut(|(_, stream)| {
+            if net::write_frame(stream, message).is_ok() {
+                return true;
             }
-            "move_blocker" => {
-                if player_type == "blocker" && !self.blocker.moved && action.len() == 3 {
-                    let x: i16 = action[1].parse().unwrap();
-                    let y: i16 = action[2].parse().unwrap();
-                    let new_block = (x, y);
-                    if !self.blocker.blocked_squares.contains(&new_block) {
-                        self.blocker.blocked_squares.push(new_block);
-                        self.blocker.moved = true;
-                        self.current_player = String::from("runner");
-
-                        let mut can_move = false;
-                        for dx in -self.runner.power..=self.runner.power {
-                            for dy in -self.runner.power..=self.runner.power {
-                                let test_pos = (self.runner.position.0 + dx, self.runner.position.1 + dy);
-                                if test_pos.0 >= 0 && test_pos.0 < SQUARES &&
-                                    test_pos.1 >= 0 && test_pos.1 < SQUARES &&
-                                    !self.blocker.blocked_squares.contains(&test_pos) {
-                                    can_move = true;
-                                    break;
-                                }
-                            }
-                        }
-                        if !can_move {
-                            self.game_over = true;
-                            self.game_state = GameState::GameOver(false);
-                        }
-                    }
-                }
+            // Wake the reader thread so it runs the disconnect handling.
+            let _ = stream.shutdown(Shutdown::Both);
+            false
+        });
+    }
+
+    fn welcome(&self, player_type: Role) -> ServerMessage {
+        let token = self
+            .seats
+            .iter()
+            .find(|seat| seat.role == player_type)
+            .map(|seat| seat.token.clone())
+            .unwrap_or_default();
+        ServerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            room: self.name.clone(),
+            role: player_type,
+            token,
+            game: self.game.clone(),
+        }
+    }
+
+    /// Puts `game` on the board, playing if everyone is seated. A bot whose
+    /// turn it is moves once [`run_bots`] is called.
+    fn start(&mut self, game: Game) -> Result<(), GameError> {
+        self.game = game;
+        self.epoch += 1;
+        if !self.game.is_over() {
+            self.game.game_state = if self.seats.iter().any(|seat| seat.connection.is_none()) {
+                GameState::CPUMode
+            } else if self.seats.len() == 2 {
+                GameState::Playing
+            } else {
+                GameState::WaitingForPlayers
+            };
+            self.game.end_if_trapped();
+        }
+        self.play_turns()
+    }
+
+    /// Drops a pending takeback request, telling everyone it lapsed.
+    fn cancel_takeback(&mut self) {
+        if self.takeback.take().is_some() {
+            self.broadcast(&ServerMessage::TakebackAnswered { accepted: false });
+        }
+    }
+
+    fn answer_takeback(&mut self, requester: Role, accept: bool) -> Result<(), GameError> {
+        self.takeback = None;
+        self.broadcast(&ServerMessage::TakebackAnswered { accepted: accept });
+        if !accept {
+            return Ok(());
+        }
+        let mut game = self.game.clone();
+        game.take_back(requester);
+        println!("{} took back a move in room {:?}", requester, self.name);
+        self.start(game)
+    }
+
+    /// Applies remote players' queued moves until the seat to move has
+    /// nothing to play. Bots are left to [`run_bots`].
+    fn play_turns(&mut self) -> Result<(), GameError> {
+        while matches!(self.game.game_state, GameState::Playing | GameState::CPUMode) && !self.game.is_over() {
+            let current = self.game.current_player;
+            let Some(seat) = self.seats.iter_mut().find(|seat| seat.role == current && seat.connection.is_some())
+            else {
+                break;
+            };
+            if !agent::step(&mut self.game, seat.agent.as_mut())? {
+                break;
             }
-            _ => {}
         }
+        Ok(())
+    }
 
-        if self.runner.moved && self.blocker.moved {
-            self.runner.moved = false;
-            self.blocker.moved = false;
-            self.turn_count += 1;
+    fn bots_turn(&self) -> bool {
+        matches!(self.game.game_state, GameState::Playing | GameState::CPUMode)
+            && !self.game.is_over()
+            && self
+                .seats
+                .iter()
+                .any(|seat| seat.role == self.game.current_player && seat.connection.is_none())
+    }
+
+    fn summary(&self) -> RoomSummary {
+        RoomSummary {
+            name: self.name.clone(),
+            players: self.seats.len(),
+            state: self.game.game_state,
         }
+    }
+}
 
-        true
+/// Plays bots' moves while it is their turn, on a thread of their own and
+/// without holding the room lock while they think, so a slow search never
+/// holds up the players or the lobby.
+fn run_bots(room: &Arc<Mutex<Room>>) {
+    {
+        let mut locked = room.lock().unwrap();
+        if locked.bot_thinking || !locked.bots_turn() {
+            return;
+        }
+        locked.bot_thinking = true;
     }
+
+    let room = Arc::clone(room);
+    thread::spawn(move || loop {
+        let mut locked = room.lock().unwrap();
+        if !locked.bots_turn() {
+            locked.bot_thinking = false;
+            return;
+        }
+        let current = locked.game.current_player;
+        let Some(seat) = locked.seats.iter_mut().find(|seat| seat.role == current) else {
+            locked.bot_thinking = false;
+            return;
+        };
+        let mut bot = mem::replace(&mut seat.agent, Box::new(Thinking));
+        let game = locked.game.clone();
+        let epoch = locked.epoch;
+        drop(locked);
+
+        let pos = bot.choose_move(&game);
+
+        let mut locked = room.lock().unwrap();
+        if let Some(seat) = locked.seats.iter_mut().find(|seat| seat.role == current && seat.connection.is_none()) {
+            seat.agent = bot;
+        }
+        if locked.epoch != epoch || locked.game.moves.len() != game.moves.len() || !locked.bots_turn() {
+            // The game moved on while the bot was thinking; look again.
+            drop(locked);
+            continue;
+        }
+        let Some(pos) = pos else {
+            locked.bot_thinking = false;
+            return;
+        };
+        if let Err(e) = locked.game.play(current, pos).and_then(|()| locked.play_turns()) {
+            println!("CPU move rejected in room {:?}: {}", locked.name, e);
+            locked.bot_thinking = false;
+            return;
+        }
+        let update = ServerMessage::StateUpdate { game: locked.game.clone() };
+        locked.broadcast(&update);
+    });
 }
 
-fn simulate_cpu_runner(game: &mut Game) {
-    let mut rng = rand::thread_rng();
-    let mut possible_moves = Vec::new();
+fn new_game(config: &Config) -> Game {
+    let mut game = Game::new(config.squares, config.power, config.goal);
+    // The setup keeps a way open under the rules the game is played with.
+    game.end_when_sealed = config.end_when_sealed;
+    game.turn_limit = config.turn_limit;
+    game.rule = config.rule;
+    let setup = Setup {
+        pattern: config.pattern,
+        blocks: config.blocks,
+        margin: 1,
+        seed: match config.seed {
+            Some(seed) => seed.wrapping_add(GAMES_DEALT.fetch_add(1, Ordering::Relaxed)),
+            None => rand::thread_rng().gen(),
+        },
+    };
+    setup.apply(&mut game);
+    println!("New {} position from seed {}", setup.pattern, setup.seed);
+    game
+}
 
-    for dx in -game.runner.power..=game.runner.power {
-        for dy in -game.runner.power..=game.runner.power {
-            let new_pos = (game.runner.position.0 + dx, game.runner.position.1 + dy);
+fn cpu_agent(config: &Config, difficulty: Difficulty) -> Box<dyn Agent + Send> {
+    let Some(command) = &config.engine else {
+        return Box::new(CpuAgent::new(difficulty));
+    };
+    match EngineAgent::spawn(command) {
+        Ok(engine) => {
+            println!("Seating engine {:?}", engine.name);
+            Box::new(engine)
+        }
+        Err(e) => {
+            println!("Could not start engine {:?} ({}); using the built-in CPU", command, e);
+            Box::new(CpuAgent::new(difficulty))
+        }
+    }
+}
 
-            if new_pos.0 >= 0 && new_pos.0 < SQUARES &&
-                new_pos.1 >= 0 && new_pos.1 < SQUARES &&
-                !game.blocker.blocked_squares.contains(&new_pos) {
+/// `bot` is the agent to seat for a `RequestCpu`, started beforehand.
+fn handle_message(room: &mut Room, message: ClientMessage, player_type: Role, bot: Option<Box<dyn Agent + Send>>) -> ServerMessage {
+    if matches!(
+        message,
+        ClientMessage::Move { .. }
+            | ClientMessage::Resign
+            | ClientMessage::NewGame
+            | ClientMessage::Load { .. }
+            | ClientMessage::RequestCpu { .. }
+    ) {
+        room.cancel_takeback();
+    }
 
-                if new_pos.0 == 0 || new_pos.0 == SQUARES - 1 ||
-                    new_pos.1 == 0 || new_pos.1 == SQUARES - 1 {
-                    for _ in 0..3 {
-                        possible_moves.push(new_pos);
+    let result = match message {
+        ClientMessage::Join { .. } | ClientMessage::Resume { .. } => return room.welcome(player_type),
+        ClientMessage::ListRooms | ClientMessage::Poll | ClientMessage::Heartbeat => Ok(()),
+        ClientMessage::RequestCpu { role, difficulty } => {
+            if matches!(room.game.game_state, GameState::WaitingForPlayers) {
+                if let Some(seat) = room.seats.iter_mut().find(|seat| seat.role == player_type) {
+                    seat.role = role;
+                }
+                let bot = bot.unwrap_or_else(|| Box::new(CpuAgent::new(difficulty)));
+                room.seats.push(Seat::bot(role.opponent(), bot));
+                room.game.game_state = GameState::CPUMode;
+                println!("{} plays the {} CPU in room {:?}", role, difficulty, room.name);
+                // The seat may have switched roles, so resend the whole welcome.
+                return room.welcome(role);
+            } else {
+                Err(GameError::AlreadyStarted)
+            }
+        }
+        ClientMessage::Move { x, y } => {
+            if room.game.is_over() {
+                Err(GameError::GameOver)
+            } else if matches!(room.game.game_state, GameState::WaitingForPlayers) {
+                Err(GameError::NotStarted)
+            } else if room.game.current_player != player_type {
+                Err(GameError::NotYourTurn)
+            } else {
+                if let Some(seat) = room.seats.iter_mut().find(|seat| seat.role == player_type) {
+                    seat.agent.submit((x, y));
+                }
+                room.play_turns()
+            }
+        }
+        ClientMessage::Resign => room.game.resign(player_type),
+        ClientMessage::RequestTakeback => {
+            if !room.game.moves.iter().any(|m| m.role == player_type) {
+                Err(GameError::NothingToTakeBack)
+            } else {
+                let answer = room
+                    .seats
+                    .iter()
+                    .find(|seat| seat.role == player_type.opponent())
+                    .and_then(|seat| seat.agent.accepts_takeback());
+                match answer {
+                    Some(accept) => room.answer_takeback(player_type, accept),
+                    None => {
+                        room.takeback = Some(player_type);
+                        room.broadcast(&ServerMessage::TakebackRequested { role: player_type });
+                        Ok(())
                     }
-                } else {
-                    possible_moves.push(new_pos);
                 }
             }
         }
-    }
+        ClientMessage::AnswerTakeback { accept } => match room.takeback {
+            Some(requester) if requester == player_type.opponent() => room.answer_takeback(requester, accept),
+            _ => Err(GameError::NoTakebackRequested),
+        },
+        ClientMessage::NewGame => {
+            if room.game.is_over() {
+                let game = new_game(&room.config);
+                room.start(game)
+            } else {
+                Err(GameError::GameInProgress)
+            }
+        }
+        ClientMessage::Load { save } => {
+            if !room.config.plays(&save) {
+                Err(GameError::BadSave(format!("this room plays on a {}", room.config.describe())))
+            } else if room.game.is_over() || matches!(room.game.game_state, GameState::WaitingForPlayers) {
+                match save.restore() {
+                    Ok(game) => {
+                        println!("{} loaded a saved game in room {:?}", player_type, room.name);
+                        room.start(game)
+                    }
+                    Err(reason) => Err(GameError::BadSave(reason)),
+                }
+            } else {
+                Err(GameError::GameInProgress)
+            }
+        }
+    };
 
-    if let Some(&new_pos) = possible_moves.choose(&mut rng) {
-        game.runner.position = new_pos;
-        game.runner.moved = true;
-        game.current_player = String::from("blocker");
+    match result {
+        Ok(()) => ServerMessage::StateUpdate { game: room.game.clone() },
+        Err(error) => {
+            println!("Rejected {} message in room {:?}: {}", player_type, room.name, error);
+            ServerMessage::Error { error }
+        }
+    }
+}
 
-        if new_pos.0 == 0 || new_pos.0 == SQUARES - 1 ||
-            new_pos.1 == 0 || new_pos.1 == SQUARES - 1 {
-            game.won = true;
-            game.game_state = GameState::GameOver(true);
+/// The settings for a room a client may be about to create. Clients only get
+/// games the CPU can still answer quickly; the server's own settings are
+/// left to whoever runs it.
+fn room_config(config: &Config, power: Option<i16>, rule: Option<MovementRule>) -> Result<Config, String> {
+    let chosen = power.is_some() || rule.is_some();
+    let mut config = config.clone();
+    if let Some(power) = power {
+        if !(1..=MAX_POWER).contains(&power) {
+            return Err(format!("power must be between 1 and {}", MAX_POWER));
         }
+        config.power = power;
     }
+    if let Some(rule) = rule {
+        if !rule.is_valid() {
+            return Err(format!("invalid movement rule {}", rule));
+        }
+        config.rule = rule;
+    }
+    if chosen && ai::search_cost(config.squares, config.rule, config.power) > ai::MAX_SEARCH_COST {
+        return Err(format!(
+            "power {} with {} moves is too much for the CPU on a {}x{} board",
+            config.power, config.rule, config.squares, config.squares
+        ));
+    }
+    Ok(config)
+}
+
+/// Seats the player in room `name`, creating it with `config` if needed.
+fn join_room(
+    lobby: &Lobby,
+    name: &str,
+    preferred: Option<Role>,
+    connection: u64,
+    config: &Config,
+) -> Result<(Arc<Mutex<Room>>, Role), String> {
+    let mut rooms = lobby.lock().unwrap();
+    let room = rooms
+        .entry(name.to_string())
+        .or_insert_with(|| Arc::new(Mutex::new(Room::new(name, config))));
+
+    let mut locked = room.lock().unwrap();
+    if matches!(locked.game.game_state, GameState::CPUMode) {
+        return Err(format!("Room {:?} is playing against the CPU", name));
+    }
+    let order = match preferred {
+        Some(role) => [role, role.opponent()],
+        None => [Role::Blocker, Role::Runner],
+    };
+    let Some(player_type) = order
+        .into_iter()
+        .find(|role| !locked.seats.iter().any(|seat| seat.role == *role))
+    else {
+        return Err(format!("Room {:?} is full", name));
+    };
+    locked.seats.push(Seat::remote(player_type, connection));
+    if locked.seats.len() == 2 && matches!(locked.game.game_state, GameState::WaitingForPlayers) {
+        locked.game.game_state = GameState::Playing;
+        println!("Game started in room {:?}!", name);
+    }
+    println!("{} joined room {:?}", player_type, name);
+    drop(locked);
+
+    Ok((Arc::clone(room), player_type))
 }
 
-fn handle_client(mut stream: std::net::TcpStream, game: Arc<Mutex<Game>>, player_type: String) {
+fn resume_seat(lobby: &Lobby, token: &str, connection: u64) -> Result<(Arc<Mutex<Room>>, Role), String> {
+    let rooms = lobby.lock().unwrap();
+    for room in rooms.values() {
+        let mut locked = room.lock().unwrap();
+        let Some(seat) = locked.seats.iter_mut().find(|seat| !seat.token.is_empty() && seat.token == token) else {
+            continue;
+        };
+
+        let player_type = seat.role;
+        let previous = seat.connection;
+        seat.connection = Some(connection);
+        seat.disconnected_at = None;
+
+        // The old stream may still look alive if it dropped without a FIN.
+        locked.active_connections.retain(|(id, stream)| {
+            if Some(*id) != previous {
+                return true;
+            }
+            let _ = stream.shutdown(Shutdown::Both);
+            false
+        });
+        println!("{} resumed in room {:?}", player_type, locked.name);
+        locked.broadcast(&ServerMessage::PlayerReturned { role: player_type });
+        drop(locked);
+
+        return Ok((Arc::clone(room), player_type));
+    }
+    Err(String::from("Unknown or expired session"))
+}
+
+fn leave_room(lobby: &Lobby, room: &Arc<Mutex<Room>>, player_type: Role, connection: u64) {
+    let disconnected_at = Instant::now();
+    {
+        let mut locked = room.lock().unwrap();
+        locked.active_connections.retain(|(id, _)| *id != connection);
+        let Some(seat) = locked
+            .seats
+            .iter_mut()
+            .find(|seat| seat.role == player_type && seat.connection == Some(connection))
+        else {
+            return;
+        };
+        seat.disconnected_at = Some(disconnected_at);
+        println!("{} disconnected from room {:?}", player_type, locked.name);
+        locked.broadcast(&ServerMessage::PlayerLeft {
+            role: player_type,
+            grace_secs: GRACE_PERIOD.as_secs(),
+        });
+    }
+
+    let lobby = Arc::clone(lobby);
+    let room = Arc::clone(room);
+    thread::spawn(move || {
+        thread::sleep(GRACE_PERIOD);
+        let mut rooms = lobby.lock().unwrap();
+        let mut locked = room.lock().unwrap();
+        let still_gone = locked
+            .seats
+            .iter()
+            .any(|seat| seat.role == player_type && seat.disconnected_at == Some(disconnected_at));
+        if !still_gone {
+            return;
+        }
+
+        locked.seats.retain(|seat| seat.role != player_type);
+        let in_progress = matches!(locked.game.game_state, GameState::Playing | GameState::CPUMode);
+        locked.cancel_takeback();
+        if in_progress && locked.game.resign(player_type).is_ok() {
+            println!("{} forfeited in room {:?}", player_type, locked.name);
+            let update = ServerMessage::StateUpdate { game: locked.game.clone() };
+            locked.broadcast(&update);
+        }
+        if locked.seats.iter().all(|seat| seat.connection.is_none()) {
+            println!("Closing empty room {:?}", locked.name);
+            rooms.remove(&locked.name);
+        }
+    });
+}
+
+fn list_rooms(lobby: &Lobby) -> ServerMessage {
+    // Let go of the lobby before waiting on any room.
+    let rooms: Vec<Arc<Mutex<Room>>> = lobby.lock().unwrap().values().cloned().collect();
+    let mut rooms: Vec<RoomSummary> = rooms.iter().map(|room| room.lock().unwrap().summary()).collect();
+    rooms.sort_by(|a, b| a.name.cmp(&b.name));
+    ServerMessage::RoomList { rooms }
+}
+
+fn attach(stream: &mut TcpStream, room: &Arc<Mutex<Room>>, player_type: Role, connection: u64) -> io::Result<()> {
+    let mut locked = room.lock().unwrap();
+    net::write_frame(stream, &locked.welcome(player_type))?;
+    locked.active_connections.push((connection, stream.try_clone()?));
+    let update = ServerMessage::StateUpdate { game: locked.game.clone() };
+    locked.broadcast(&update);
+    Ok(())
+}
+
+fn handle_client(mut stream: TcpStream, lobby: Lobby, config: &Config) {
+    let connection = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+    let mut seat: Option<(Arc<Mutex<Room>>, Role)> = None;
+    if stream.set_read_timeout(Some(HEARTBEAT_TIMEOUT)).is_err() || stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+        return;
+    }
+
     loop {
-        let mut buffer = [0; 512];
-        match stream.read(&mut buffer) {
-            Ok(n) => {
-                if n == 0 {
+        let message = match net::read_frame(&mut stream) {
+            Ok(ClientMessage::Heartbeat) => continue,
+            Ok(message) => message,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                let response = ServerMessage::Error {
+                    error: GameError::MalformedCommand(e.to_string()),
+                };
+                if net::write_frame(&mut stream, &response).is_err() {
                     break;
                 }
-                let action = String::from_utf8_lossy(&buffer[..n]);
-                let mut game = game.lock().unwrap();
-
-                if action.trim() == "activate_cpu" && matches!(game.game_state, GameState::WaitingForPlayers) {
-                    game.game_state = GameState::CPUMode;
-                    if game.current_player == "runner" {
-                        simulate_cpu_runner(&mut game);
-                    }
-                    let response = serde_json::to_string(&(game.clone(), player_type.clone(), true)).unwrap();
-                    if let Err(_) = stream.write_all(response.as_bytes()) {
-                        break;
+                continue;
+            }
+            Err(_) => break,
+        };
+
+        let joined = match (&mut seat, message) {
+            (_, ClientMessage::ListRooms) => Err(list_rooms(&lobby)),
+            (None, ClientMessage::Join { version, .. } | ClientMessage::Resume { version, .. })
+                if version != PROTOCOL_VERSION =>
+            {
+                Err(ServerMessage::Refused {
+                    reason: format!("protocol version {} is not supported, expected {}", version, PROTOCOL_VERSION),
+                })
+            }
+            (None, ClientMessage::Join { room, role, power, rule, .. }) => room_config(config, power, rule)
+                .and_then(|config| join_room(&lobby, &room, role, connection, &config))
+                .map_err(|reason| ServerMessage::Refused { reason }),
+            (None, ClientMessage::Resume { token, .. }) => resume_seat(&lobby, &token, connection)
+                .map_err(|reason| ServerMessage::Refused { reason }),
+            (None, _) => Err(ServerMessage::Error {
+                error: GameError::NotJoined,
+            }),
+            (Some((room, player_type)), message) => {
+                // Starting an engine can take a while, so do it before locking the room.
+                let bot = match &message {
+                    ClientMessage::RequestCpu { difficulty, .. } => {
+                        let config = room.lock().unwrap().config.clone();
+                        Some(cpu_agent(&config, *difficulty))
                     }
-                    continue;
+                    _ => None,
+                };
+                let mut locked = room.lock().unwrap();
+                let is_poll = matches!(message, ClientMessage::Poll | ClientMessage::ListRooms);
+                let response = handle_message(&mut locked, message, *player_type, bot);
+                if let ServerMessage::Welcome { role, .. } = &response {
+                    *player_type = *role;
                 }
-                if action.trim() == "poll" && matches!(game.game_state, GameState::CPUMode) {
-                    if game.current_player == "runner" {
-                        simulate_cpu_runner(&mut game);
-                    }
-                    let response = serde_json::to_string(&(game.clone(), player_type.clone(), true)).unwrap();
-                    if let Err(_) = stream.write_all(response.as_bytes()) {
-                        break;
-                    }
+                if !is_poll && matches!(response, ServerMessage::StateUpdate { .. }) {
+                    locked.broadcast(&response);
+                    drop(locked);
+                    run_bots(room);
                     continue;
                 }
+                Err(response)
+            }
+        };
 
-                let success = game.update(&action, &player_type);
-
-                if success && matches!(game.game_state, GameState::CPUMode) {
-                    if game.current_player == "runner" {
-                        simulate_cpu_runner(&mut game);
-                    }
+        match joined {
+            Ok((room, player_type)) => {
+                let attached = attach(&mut stream, &room, player_type, connection);
+                seat = Some((room, player_type));
+                if attached.is_err() {
+                    break;
                 }
-
-                let response = serde_json::to_string(&(game.clone(), player_type.clone(), success)).unwrap();
-                if let Err(_) = stream.write_all(response.as_bytes()) {
+            }
+            Err(response) => {
+                if net::write_frame(&mut stream, &response).is_err() {
                     break;
                 }
             }
-            Err(_) => break,
+        }
+        // Only now, so a bot's move reaches the client after the answer
+        // to its message.
+        if let Some((room, _)) = &seat {
+            run_bots(room);
         }
     }
+
+    if let Some((room, player_type)) = seat {
+        leave_room(&lobby, &room, player_type, connection);
+    }
 }
-fn main() {
-    let listener = TcpListener::bind("127.0.0.1:25567").unwrap();
-    let game = Arc::new(Mutex::new(Game::new()));
-    let mut player_count = 0;
-    let active_connections = Arc::new(Mutex::new(Vec::new()));
 
-    println!("Server started, waiting for players...");
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {:?} for {}", value, flag))
+}
 
-    for stream in listener.incoming() {
-        match stream {
-            Ok(mut stream) => {
-                let mut connections = active_connections.lock().unwrap();
+fn parse_args() -> Result<(String, Config, Option<PathBuf>), String> {
+    let mut bind = String::from("127.0.0.1");
+    let mut port: u16 = 25567;
+    let mut resume = None;
+    let mut density: Option<f64> = None;
+    let mut config = Config {
+        squares: 21,
+        power: 1,
+        rule: MovementRule::Chebyshev,
+        goal: Goal::AnyEdge,
+        turn_limit: None,
+        blocks: 42,
+        pattern: Pattern::Uniform,
+        seed: None,
+        end_when_sealed: false,
+        engine: None,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--bind" => bind = parse_value(&flag, args.next())?,
+            "--port" => port = parse_value(&flag, args.next())?,
+            "--size" => config.squares = parse_value(&flag, args.next())?,
+            "--power" => config.power = parse_value(&flag, args.next())?,
+            "--movement" => config.rule = parse_value(&flag, args.next())?,
+            "--unbounded" => config.goal = Goal::Survive,
+            "--turn-limit" => config.turn_limit = Some(parse_value(&flag, args.next())?),
+            "--blocks" => config.blocks = parse_value(&flag, args.next())?,
+            "--density" => density = Some(parse_value(&flag, args.next())?),
+            "--pattern" => config.pattern = parse_value(&flag, args.next())?,
+            "--seed" => config.seed = Some(parse_value(&flag, args.next())?),
+            "--end-when-sealed" => config.end_when_sealed = true,
+            "--engine" => config.engine = Some(parse_value(&flag, args.next())?),
+            "--resume" => resume = Some(parse_value(&flag, args.next())?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(format!("unknown argument {:?}", flag)),
+        }
+    }
 
-                if player_count >= 2 {
-                    let response = serde_json::to_string(&("Game full".to_string())).unwrap();
-                    let _ = stream.write_all(response.as_bytes());
-                    continue;
-                }
+    if !(3..=MAX_SQUARES).contains(&config.squares) {
+        return Err(format!("--size must be between 3 and {}", MAX_SQUARES));
+    }
+    if !(1..=MAX_POWER).contains(&config.power) {
+        return Err(format!("--power must be between 1 and {}", MAX_POWER));
+    }
+    if config.turn_limit.is_some_and(|limit| limit < 1) {
+        return Err(String::from("--turn-limit must be at least 1"));
+    }
+    if let Some(density) = density {
+        if !(0. ..=1.).contains(&density) {
+            return Err(String::from("--density must be between 0 and 1"));
+        }
+        config.blocks = setup::blocks_for_density(config.squares, density);
+    }
+    Ok((format!("{}:{}", bind, port), config, resume))
+}
 
-                let game = Arc::clone(&game);
-                player_count += 1;
-
-                let player_type = if player_count == 1 {
-                    println!("Blocker connected!");
-                    String::from("blocker")
-                } else {
-                    println!("Runner connected!");
-                    {
-                        let mut game = game.lock().unwrap();
-                        game.game_state = GameState::Playing;
-                    }
-                    String::from("runner")
-                };
+fn main() {
+    let (address, config, resume) = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let listener = match TcpListener::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Could not bind {}: {}", address, e);
+            std::process::exit(1);
+        }
+    };
+    let lobby: Lobby = Arc::new(Mutex::new(HashMap::new()));
+    if let Some(path) = resume {
+        let resumed = SavedGame::read(&path).and_then(|save| Ok((save.restore().map_err(io::Error::other)?, save)));
+        let (game, save) = match resumed {
+            Ok(resumed) => resumed,
+            Err(e) => {
+                eprintln!("Could not resume {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        };
+        // New games in this room keep to the save's rules.
+        let mut room = Room::new(RESUME_ROOM, &config.with_rules_of(&save));
+        // Nobody is seated yet, so no bot can move and this cannot fail.
+        let _ = room.start(game);
+        println!("Resumed {} in room {:?} ({})", path.display(), RESUME_ROOM, room.config.describe());
+        lobby.lock().unwrap().insert(RESUME_ROOM.to_string(), Arc::new(Mutex::new(room)));
+    }
 
-                connections.push(stream.try_clone().unwrap());
+    println!("Server started on {} ({}), waiting for players...", address, config.describe());
 
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let lobby = Arc::clone(&lobby);
+                let config = config.clone();
                 thread::spawn(move || {
-                    handle_client(stream, game, player_type);
+                    handle_client(stream, lobby, &config);
                 });
-
-                if player_count == 2 {
-                    println!("Game started!");
-                }
             }
             Err(e) => {
                 eprintln!("Error accepting connection: {}", e);
@@ -273,4 +822,3 @@ fn main() {
         }
     }
 }
-
@@ -0,0 +1,199 @@
+//! A line-based protocol for bots written as separate programs, in the
+//! spirit of chess's UCI. The engine reads commands on stdin and answers on
+//! stdout, one per line; squares are written `x,y`.
+//!
+//! - `angel`: handshake. Answer with any `id name <name>` lines, then `angelok`.
+//! - `setoption name <size|power|goal|movement> value <value>`: sent before
+//!   the first position and whenever they change. `goal` is `edge`, `top` or
+//!   `survive`; with `survive` the board is unbounded and `size` is only the
+//!   width of the area around the runner worth looking at. `movement` is
+//!   `chebyshev`, `manhattan`, `euclidean`, `leaper:<a>,<b>` or `fool`, see
+//!   [`MovementRule`].
+//! - `isready`: answer `readyok` once earlier commands are dealt with.
+//! - `newgame`: the next position belongs to a fresh game.
+//! - `position runner <x,y> blocks [<x,y> ...]`: the current board.
+//! - `go runner` or `go blocker`: answer `bestmove <x,y>` for that side.
+//! - `quit`: exit.
+//!
+//! Lines an engine does not understand are ignored, in both directions.
+
+use crate::agent::{Agent, CpuAgent};
+use crate::ai::Difficulty;
+use crate::{Game, Goal, MovementRule, Point, Role};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an engine gets to answer the handshake, and to answer each `go`.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const MOVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn format_point(pos: Point) -> String {
+    format!("{},{}", pos.0, pos.1)
+}
+
+pub fn parse_point(s: &str) -> Option<Point> {
+    let (x, y) = s.split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+pub fn format_goal(goal: Goal) -> &'static str {
+    match goal {
+        Goal::TopRow => "top",
+        Goal::AnyEdge => "edge",
+        Goal::Survive => "survive",
+    }
+}
+
+pub fn parse_goal(s: &str) -> Option<Goal> {
+    match s {
+        "top" => Some(Goal::TopRow),
+        "edge" => Some(Goal::AnyEdge),
+        "survive" => Some(Goal::Survive),
+        _ => None,
+    }
+}
+
+/// An engine subprocess filling a seat. If it dies or answers nonsense the
+/// built-in CPU takes over, so the game is never left waiting on it.
+pub struct EngineAgent {
+    pub name: String,
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines from the engine's stdout, read on their own thread so a silent
+    /// engine can be given up on.
+    lines: Receiver<io::Result<String>>,
+    options: Option<(i16, i16, Goal, MovementRule)>,
+    fallback: Option<CpuAgent>,
+}
+
+impl EngineAgent {
+    /// Starts `command` (a program followed by whitespace-separated
+    /// arguments) and completes the handshake.
+    pub fn spawn(command: &str) -> io::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty engine command"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            return Err(io::Error::other("engine pipes unavailable"));
+        };
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut engine = EngineAgent {
+            name: program.to_string(),
+            child,
+            stdin,
+            lines,
+            options: None,
+            fallback: None,
+        };
+
+        engine.send("angel")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let line = engine.read_line(deadline)?;
+            if line == "angelok" {
+                break;
+            }
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.to_string();
+            }
+        }
+        Ok(engine)
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", line)?;
+        self.stdin.flush()
+    }
+
+    fn read_line(&mut self, deadline: Instant) -> io::Result<String> {
+        match self.lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(line) => Ok(line?.trim().to_string()),
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(io::ErrorKind::TimedOut, "engine took too long")),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "engine exited")),
+        }
+    }
+
+    fn best_move(&mut self, game: &Game) -> io::Result<Point> {
+        let options = (game.squares, game.power, game.goal, game.rule);
+        if self.options != Some(options) {
+            self.send(&format!("setoption name size value {}", game.squares))?;
+            self.send(&format!("setoption name power value {}", game.power))?;
+            self.send(&format!("setoption name goal value {}", format_goal(game.goal)))?;
+            self.send(&format!("setoption name movement value {}", game.rule))?;
+            self.send("newgame")?;
+            self.options = Some(options);
+        }
+
+        let blocks: Vec<String> = game.blocker.blocked_squares.iter().map(|pos| format_point(*pos)).collect();
+        self.send(&format!(
+            "position runner {} blocks {}",
+            format_point(game.runner.position),
+            blocks.join(" ")
+        ))?;
+        self.send(&format!("go {}", game.current_player))?;
+
+        let deadline = Instant::now() + MOVE_TIMEOUT;
+        loop {
+            let line = self.read_line(deadline)?;
+            if let Some(answer) = line.strip_prefix("bestmove ") {
+                return parse_point(answer).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("bad bestmove {:?}", answer))
+                });
+            }
+        }
+    }
+}
+
+impl Agent for EngineAgent {
+    fn choose_move(&mut self, game: &Game) -> Option<Point> {
+        if let Some(fallback) = &mut self.fallback {
+            return fallback.choose_move(game);
+        }
+
+        let pos = self.best_move(game).map_err(|e| e.to_string()).and_then(|pos| {
+            let legal = match game.current_player {
+                Role::Runner => game.check_runner_move(pos),
+                Role::Blocker => game.check_block(pos),
+            };
+            legal.map(|()| pos).map_err(|e| format!("{} at {}", e, format_point(pos)))
+        });
+        match pos {
+            Ok(pos) => Some(pos),
+            Err(e) => {
+                eprintln!("Engine {} failed ({}); the built-in CPU takes over.", self.name, e);
+                let fallback = self.fallback.insert(CpuAgent::new(Difficulty::Medium));
+                fallback.choose_move(game)
+            }
+        }
+    }
+
+    fn accepts_takeback(&self) -> Option<bool> {
+        Some(true)
+    }
+}
+
+impl Drop for EngineAgent {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,894 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+pub mod agent;
+pub mod ai;
+pub mod engine;
+pub mod net;
+pub mod protocol;
+pub mod save;
+pub mod setup;
+
+pub type Point = (i16, i16);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameState {
+    WaitingForPlayers,
+    Playing,
+    GameOver(bool),
+    CPUMode,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Runner,
+    Blocker,
+}
+
+impl Role {
+    pub fn opponent(self) -> Role {
+        match self {
+            Role::Runner => Role::Blocker,
+            Role::Blocker => Role::Runner,
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "runner" => Ok(Role::Runner),
+            "blocker" => Ok(Role::Blocker),
+            _ => Err(format!("unknown role {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Runner => write!(f, "runner"),
+            Role::Blocker => write!(f, "blocker"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveError {
+    AlreadyMoved,
+    OutOfBounds,
+    OutOfReach,
+    Blocked,
+    RunnerSquare,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::AlreadyMoved => write!(f, "you already moved this turn"),
+            MoveError::OutOfBounds => write!(f, "square is outside the board"),
+            MoveError::OutOfReach => write!(f, "square is out of the runner's reach"),
+            MoveError::Blocked => write!(f, "square is already blocked"),
+            MoveError::RunnerSquare => write!(f, "cannot block the runner's square"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum GameError {
+    MalformedCommand(String),
+    UnknownCommand(String),
+    NotYourTurn,
+    IllegalMove(MoveError),
+    GameOver,
+    NotStarted,
+    AlreadyStarted,
+    GameInProgress,
+    NotJoined,
+    BadSave(String),
+    NothingToTakeBack,
+    NoTakebackRequested,
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::MalformedCommand(action) => write!(f, "malformed command {:?}", action),
+            GameError::UnknownCommand(command) => write!(f, "unknown command {:?}", command),
+            GameError::NotYourTurn => write!(f, "it is not your turn"),
+            GameError::IllegalMove(e) => write!(f, "illegal move: {}", e),
+            GameError::GameOver => write!(f, "the game is already over"),
+            GameError::NotStarted => write!(f, "the game has not started yet"),
+            GameError::AlreadyStarted => write!(f, "the game has already started"),
+            GameError::GameInProgress => write!(f, "the current game is not over yet"),
+            GameError::NotJoined => write!(f, "join a room first"),
+            GameError::BadSave(reason) => write!(f, "cannot load saved game: {}", reason),
+            GameError::NothingToTakeBack => write!(f, "you have no move to take back"),
+            GameError::NoTakebackRequested => write!(f, "there is no takeback to answer"),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl From<MoveError> for GameError {
+    fn from(e: MoveError) -> Self {
+        GameError::IllegalMove(e)
+    }
+}
+
+/// Which squares the runner has to reach to win.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Goal {
+    TopRow,
+    AnyEdge,
+    /// No edges and nothing to reach: the board goes on forever and the
+    /// runner wins only by lasting until the turn limit, if there is one.
+    Survive,
+}
+
+/// Which squares the runner can reach in one move.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MovementRule {
+    /// Up to `power` squares along each axis, as a chess king moves.
+    #[default]
+    Chebyshev,
+    /// Up to `power` steps left, right, up and down in total.
+    Manhattan,
+    /// Anywhere within a circle of radius `power`.
+    Euclidean,
+    /// Exactly this far along one axis and that far along the other, as a
+    /// chess knight jumps for `Leaper(2, 1)`; `power` plays no part.
+    Leaper(i16, i16),
+    /// Like [`MovementRule::Chebyshev`], but every move has to go strictly
+    /// upwards: Conway's fool.
+    Fool,
+}
+
+impl MovementRule {
+    pub fn reaches(self, from: Point, to: Point, power: i16) -> bool {
+        let dx = (to.0 - from.0).abs();
+        let dy = (to.1 - from.1).abs();
+        match self {
+            MovementRule::Chebyshev => is_within_power(from, to, power),
+            MovementRule::Manhattan => dx + dy <= power && (dx, dy) != (0, 0),
+            MovementRule::Euclidean => {
+                let (dx, dy, power) = (dx as i32, dy as i32, power as i32);
+                dx * dx + dy * dy <= power * power && (dx, dy) != (0, 0)
+            }
+            MovementRule::Leaper(a, b) => (dx, dy) == (a, b) || (dx, dy) == (b, a),
+            MovementRule::Fool => is_within_power(from, to, power) && to.1 < from.1,
+        }
+    }
+
+    /// How far along either axis a single move can go.
+    pub fn radius(self, power: i16) -> i16 {
+        match self {
+            MovementRule::Leaper(a, b) => a.max(b),
+            _ => power,
+        }
+    }
+
+    /// Whether a game can be played with this rule; leaps have to stay
+    /// within [`MAX_POWER`] and actually go somewhere.
+    pub fn is_valid(self) -> bool {
+        match self {
+            MovementRule::Leaper(a, b) => (0..=MAX_POWER).contains(&a) && (0..=MAX_POWER).contains(&b) && (a, b) != (0, 0),
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for MovementRule {
+    type Err = String;
+
+    /// Also takes `knight` for `leaper:2,1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rule = match s {
+            "chebyshev" => MovementRule::Chebyshev,
+            "manhattan" => MovementRule::Manhattan,
+            "euclidean" => MovementRule::Euclidean,
+            "knight" => MovementRule::Leaper(2, 1),
+            "fool" => MovementRule::Fool,
+            _ => {
+                let leap = s.strip_prefix("leaper:").and_then(|leap| leap.split_once(','));
+                match leap.map(|(a, b)| (a.trim().parse(), b.trim().parse())) {
+                    Some((Ok(a), Ok(b))) => MovementRule::Leaper(a, b),
+                    _ => return Err(format!("unknown movement rule {:?}", s)),
+                }
+            }
+        };
+        if !rule.is_valid() {
+            return Err(format!("leaps must be between 0 and {} squares, and not both 0", MAX_POWER));
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for MovementRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovementRule::Chebyshev => write!(f, "chebyshev"),
+            MovementRule::Manhattan => write!(f, "manhattan"),
+            MovementRule::Euclidean => write!(f, "euclidean"),
+            MovementRule::Leaper(a, b) => write!(f, "leaper:{},{}", a, b),
+            MovementRule::Fool => write!(f, "fool"),
+        }
+    }
+}
+
+/// The largest runner power a game can be set up with.
+pub const MAX_POWER: i16 = 100;
+
+/// The largest board, or area of an unbounded board, a game can have; the
+/// searches over it grow with the square of this.
+pub const MAX_SQUARES: i16 = 101;
+
+/// How far an unbounded board really goes: further than any game gets, and
+/// near enough that arithmetic on squares cannot overflow.
+const UNBOUNDED_EDGE: i16 = i16::MAX / 2;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Runner {
+    pub position: Point,
+    pub power: i16,
+    pub moved: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Blocker {
+    pub blocked_squares: BTreeSet<Point>,
+    pub moved: bool,
+}
+
+/// One half-turn: the square the runner moved to or the blocker blocked.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Move {
+    pub role: Role,
+    pub pos: Point,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Game {
+    pub runner: Runner,
+    pub blocker: Blocker,
+    pub game_over: bool,
+    pub won: bool,
+    pub turn_count: i32,
+    pub game_state: GameState,
+    pub squares: i16,
+    pub power: i16,
+    pub goal: Goal,
+    pub current_player: Role,
+    /// Also end the game, for the blocker, once the runner is walled off
+    /// from the goal, not only when it cannot move at all.
+    pub end_when_sealed: bool,
+    /// Every move played so far, in order.
+    pub moves: Vec<Move>,
+    pub resigned: Option<Role>,
+    /// The runner wins once this many turns are over.
+    pub turn_limit: Option<i32>,
+    pub rule: MovementRule,
+}
+
+pub fn is_within_power(start: Point, end: Point, power: i16) -> bool {
+    let dx = (start.0 - end.0).abs();
+    let dy = (start.1 - end.1).abs();
+    dx <= power && dy <= power && !(dx == 0 && dy == 0)
+}
+
+impl Game {
+    pub fn new(squares: i16, power: i16, goal: Goal) -> Self {
+        let start = match goal {
+            Goal::TopRow => (squares / 2, squares - 1),
+            Goal::AnyEdge | Goal::Survive => (squares / 2, squares / 2),
+        };
+
+        Game {
+            runner: Runner {
+                position: start,
+                power,
+                moved: false,
+            },
+            blocker: Blocker {
+                blocked_squares: BTreeSet::new(),
+                moved: false,
+            },
+            game_over: false,
+            won: false,
+            turn_count: 0,
+            game_state: GameState::WaitingForPlayers,
+            squares,
+            power,
+            goal,
+            current_player: Role::Runner,
+            end_when_sealed: false,
+            moves: Vec::new(),
+            resigned: None,
+            turn_limit: None,
+            rule: MovementRule::Chebyshev,
+        }
+    }
+
+    /// The blocks that were on the board before the first move.
+    pub fn initial_blocks(&self) -> BTreeSet<Point> {
+        let mut blocks = self.blocker.blocked_squares.clone();
+        for m in self.moves.iter().filter(|m| m.role == Role::Blocker) {
+            blocks.remove(&m.pos);
+        }
+        blocks
+    }
+
+    /// Takes back `role`'s last move and everything played after it. Returns
+    /// false, leaving the game alone, if `role` has not moved yet.
+    pub fn take_back(&mut self, role: Role) -> bool {
+        let Some(index) = self.moves.iter().rposition(|m| m.role == role) else {
+            return false;
+        };
+        while self.moves.len() > index {
+            self.undo();
+        }
+        true
+    }
+
+    /// The game as it stood after its first `ply` moves.
+    pub fn position_at(&self, ply: usize) -> Game {
+        let mut game = Game::new(self.squares, self.power, self.goal);
+        game.game_state = GameState::Playing;
+        game.end_when_sealed = self.end_when_sealed;
+        game.turn_limit = self.turn_limit;
+        game.rule = self.rule;
+        game.blocker.blocked_squares = self.initial_blocks();
+        for m in self.moves.iter().take(ply) {
+            match m.role {
+                Role::Runner => game.move_runner(m.pos),
+                Role::Blocker => game.move_blocker(m.pos),
+            }
+        }
+        game
+    }
+
+    /// Takes back the last move, and a resignation after it, and returns the
+    /// move. A finished game goes back to `Playing`; other states are kept.
+    pub fn undo(&mut self) -> Option<Move> {
+        let last = *self.moves.last()?;
+        let state = match self.game_state {
+            GameState::GameOver(_) => GameState::Playing,
+            state => state,
+        };
+        *self = self.position_at(self.moves.len() - 1);
+        self.game_state = state;
+        Some(last)
+    }
+
+    pub fn in_bounds(&self, pos: Point) -> bool {
+        match self.goal {
+            Goal::Survive => {
+                (-UNBOUNDED_EDGE..=UNBOUNDED_EDGE).contains(&pos.0) && (-UNBOUNDED_EDGE..=UNBOUNDED_EDGE).contains(&pos.1)
+            }
+            _ => pos.0 >= 0 && pos.0 < self.squares && pos.1 >= 0 && pos.1 < self.squares,
+        }
+    }
+
+    pub fn is_goal(&self, pos: Point) -> bool {
+        match self.goal {
+            Goal::TopRow => pos.1 == 0,
+            Goal::AnyEdge => {
+                pos.0 == 0 || pos.0 == self.squares - 1 || pos.1 == 0 || pos.1 == self.squares - 1
+            }
+            Goal::Survive => false,
+        }
+    }
+
+    /// Top-left corner of the `squares` by `squares` area that is shown and
+    /// searched: the board itself, or on an unbounded board the area
+    /// centred on the runner.
+    pub fn origin(&self) -> Point {
+        match self.goal {
+            Goal::Survive => (
+                self.runner.position.0 - self.squares / 2,
+                self.runner.position.1 - self.squares / 2,
+            ),
+            _ => (0, 0),
+        }
+    }
+
+    pub fn in_area(&self, pos: Point) -> bool {
+        let (x, y) = self.origin();
+        (x..x + self.squares).contains(&pos.0) && (y..y + self.squares).contains(&pos.1)
+    }
+
+    /// Every square of the area, column by column.
+    pub fn area(&self) -> impl Iterator<Item = Point> {
+        let ((x, y), squares) = (self.origin(), self.squares);
+        (x..x + squares).flat_map(move |x| (y..y + squares).map(move |y| (x, y)))
+    }
+
+    /// The squares the runner heads for: the goal, or on an unbounded board
+    /// the edge of the area, past which nothing has been blocked nearby.
+    pub fn is_exit(&self, pos: Point) -> bool {
+        match self.goal {
+            Goal::Survive => {
+                let (x, y) = self.origin();
+                self.in_area(pos)
+                    && (pos.0 == x || pos.0 == x + self.squares - 1 || pos.1 == y || pos.1 == y + self.squares - 1)
+            }
+            _ => self.is_goal(pos),
+        }
+    }
+
+    pub fn is_blocked(&self, pos: Point) -> bool {
+        self.blocker.blocked_squares.contains(&pos)
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.won || self.game_over
+    }
+
+    pub fn check_runner_move(&self, pos: Point) -> Result<(), MoveError> {
+        if !self.in_bounds(pos) {
+            Err(MoveError::OutOfBounds)
+        } else if !self.rule.reaches(self.runner.position, pos, self.runner.power) {
+            Err(MoveError::OutOfReach)
+        } else if self.is_blocked(pos) {
+            Err(MoveError::Blocked)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn check_block(&self, pos: Point) -> Result<(), MoveError> {
+        if !self.in_bounds(pos) {
+            Err(MoveError::OutOfBounds)
+        } else if pos == self.runner.position {
+            Err(MoveError::RunnerSquare)
+        } else if self.is_blocked(pos) {
+            Err(MoveError::Blocked)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn is_valid_runner_move(&self, pos: Point) -> bool {
+        self.check_runner_move(pos).is_ok()
+    }
+
+    pub fn is_valid_block(&self, pos: Point) -> bool {
+        self.check_block(pos).is_ok()
+    }
+
+    pub fn runner_can_move(&self) -> bool {
+        !self.runner_moves_from(self.runner.position).is_empty()
+    }
+
+    /// Whether any square is left to block; always on an unbounded board.
+    pub fn blocker_can_move(&self) -> bool {
+        self.goal == Goal::Survive || self.area().any(|pos| self.is_valid_block(pos))
+    }
+
+    /// Whether the runner can still reach an exit; on an unbounded board,
+    /// whether it is not shut inside the area.
+    pub fn goal_reachable(&self) -> bool {
+        self.goal_distances().contains_key(&self.runner.position)
+    }
+
+    /// Whether the runner has lost: no legal move left or, under
+    /// `end_when_sealed`, no way left to the goal.
+    pub fn runner_trapped(&self) -> bool {
+        !self.runner_can_move() || (self.end_when_sealed && !self.goal_reachable())
+    }
+
+    /// Every square a runner standing on `from` could step to.
+    pub fn runner_moves_from(&self, from: Point) -> Vec<Point> {
+        match self.goal {
+            Goal::Survive => self.runner_moves_within(from, (-UNBOUNDED_EDGE, -UNBOUNDED_EDGE), (UNBOUNDED_EDGE, UNBOUNDED_EDGE), false),
+            _ => self.runner_moves_within(from, (0, 0), (self.squares - 1, self.squares - 1), false),
+        }
+    }
+
+    /// The runner's moves from `from` that stay inside the area; the rest
+    /// lead past the exits.
+    pub fn runner_moves_in_area(&self, from: Point) -> Vec<Point> {
+        let (x, y) = self.origin();
+        self.runner_moves_within(from, (x, y), (x + self.squares - 1, y + self.squares - 1), false)
+    }
+
+    /// The free squares of the area from which the runner could step to
+    /// `to`; the same as its moves from `to` unless the rule is one-way.
+    pub fn runner_moves_to(&self, to: Point) -> Vec<Point> {
+        let (x, y) = self.origin();
+        self.runner_moves_within(to, (x, y), (x + self.squares - 1, y + self.squares - 1), true)
+    }
+
+    /// Only looks at squares between the corners `low` and `high`, so a
+    /// large power costs no more than the board is big. With `backwards`,
+    /// `from` is where the move ends rather than where it starts.
+    fn runner_moves_within(&self, from: Point, low: Point, high: Point, backwards: bool) -> Vec<Point> {
+        let (power, radius) = (self.runner.power, self.rule.radius(self.runner.power));
+        let mut moves = Vec::new();
+        for x in (from.0 - radius).max(low.0)..=(from.0 + radius).min(high.0) {
+            for y in (from.1 - radius).max(low.1)..=(from.1 + radius).min(high.1) {
+                let reaches = if backwards {
+                    self.rule.reaches((x, y), from, power)
+                } else {
+                    self.rule.reaches(from, (x, y), power)
+                };
+                if reaches && !self.is_blocked((x, y)) {
+                    moves.push((x, y));
+                }
+            }
+        }
+        moves
+    }
+
+    /// How many runner moves each free square of the area is from an exit;
+    /// squares that cannot reach one are left out.
+    pub fn goal_distances(&self) -> HashMap<Point, u32> {
+        let mut distances = HashMap::new();
+        let mut queue = VecDeque::new();
+        for pos in self.area() {
+            if self.is_exit(pos) && !self.is_blocked(pos) {
+                distances.insert(pos, 0);
+                queue.push_back(pos);
+            }
+        }
+
+        while let Some(pos) = queue.pop_front() {
+            let next = distances[&pos] + 1;
+            for step in self.runner_moves_to(pos) {
+                if let Entry::Vacant(entry) = distances.entry(step) {
+                    entry.insert(next);
+                    queue.push_back(step);
+                }
+            }
+        }
+        distances
+    }
+
+    pub fn move_runner(&mut self, pos: Point) {
+        self.runner.position = pos;
+        self.runner.moved = true;
+        self.moves.push(Move { role: Role::Runner, pos });
+        self.current_player = Role::Blocker;
+
+        if self.is_goal(pos) {
+            self.won = true;
+            self.game_state = GameState::GameOver(true);
+        }
+        self.advance_turn();
+    }
+
+    pub fn move_blocker(&mut self, pos: Point) {
+        self.blocker.blocked_squares.insert(pos);
+        self.blocker.moved = true;
+        self.moves.push(Move { role: Role::Blocker, pos });
+        self.current_player = Role::Runner;
+        self.end_if_trapped();
+        self.advance_turn();
+    }
+
+    /// Ends the game for the blocker if the runner is to move and trapped.
+    /// Called after every block, and whenever a position is put into play,
+    /// since a setup or a save can start the runner off trapped.
+    pub fn end_if_trapped(&mut self) {
+        if !self.is_over() && self.current_player == Role::Runner && self.runner_trapped() {
+            self.game_over = true;
+            self.game_state = GameState::GameOver(false);
+        }
+    }
+
+    fn advance_turn(&mut self) {
+        if self.runner.moved && self.blocker.moved {
+            self.runner.moved = false;
+            self.blocker.moved = false;
+            self.turn_count += 1;
+            if !self.is_over() && self.turn_limit.is_some_and(|limit| self.turn_count >= limit) {
+                self.won = true;
+                self.game_state = GameState::GameOver(true);
+            }
+        }
+    }
+
+    pub fn update(&mut self, action: &str, player_type: Role) -> Result<(), GameError> {
+        let mut parts = action.split_whitespace();
+        let command = parts
+            .next()
+            .ok_or_else(|| GameError::MalformedCommand(action.to_string()))?;
+        let role = match command {
+            "move_runner" => Role::Runner,
+            "move_blocker" => Role::Blocker,
+            _ => return Err(GameError::UnknownCommand(command.to_string())),
+        };
+        let pos = match (parts.next(), parts.next(), parts.next()) {
+            (Some(x), Some(y), None) => match (x.parse(), y.parse()) {
+                (Ok(x), Ok(y)) => (x, y),
+                _ => return Err(GameError::MalformedCommand(action.to_string())),
+            },
+            _ => return Err(GameError::MalformedCommand(action.to_string())),
+        };
+
+        if role != player_type {
+            return Err(GameError::NotYourTurn);
+        }
+        self.play(player_type, pos)
+    }
+
+    pub fn play(&mut self, player_type: Role, pos: Point) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameOver);
+        }
+        if player_type != self.current_player {
+            return Err(GameError::NotYourTurn);
+        }
+
+        match player_type {
+            Role::Runner => {
+                if self.runner.moved {
+                    return Err(MoveError::AlreadyMoved.into());
+                }
+                self.check_runner_move(pos)?;
+                self.move_runner(pos);
+            }
+            Role::Blocker => {
+                if self.blocker.moved {
+                    return Err(MoveError::AlreadyMoved.into());
+                }
+                self.check_block(pos)?;
+                self.move_blocker(pos);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn resign(&mut self, player_type: Role) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameOver);
+        }
+
+        match player_type {
+            Role::Runner => self.game_over = true,
+            Role::Blocker => self.won = true,
+        }
+        self.resigned = Some(player_type);
+        self.game_state = GameState::GameOver(player_type == Role::Blocker);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing(squares: i16, power: i16, goal: Goal) -> Game {
+        let mut game = Game::new(squares, power, goal);
+        game.game_state = GameState::Playing;
+        game
+    }
+
+    #[test]
+    fn extreme_coordinates_on_an_unbounded_board_are_out_of_bounds() {
+        let mut game = playing(21, 1, Goal::Survive);
+        for pos in [(i16::MIN, 0), (0, i16::MIN), (i16::MAX, i16::MAX), (i16::MIN, i16::MIN)] {
+            assert!(!game.in_bounds(pos));
+            assert_eq!(game.play(Role::Runner, pos), Err(GameError::IllegalMove(MoveError::OutOfBounds)));
+        }
+        game.play(Role::Runner, (11, 11)).unwrap();
+        assert_eq!(game.play(Role::Blocker, (i16::MIN, 5)), Err(GameError::IllegalMove(MoveError::OutOfBounds)));
+        assert!(game.play(Role::Blocker, (-1000, 5)).is_ok());
+    }
+
+    #[test]
+    fn runner_moves_are_checked_for_bounds_reach_and_blocks() {
+        let mut game = playing(9, 1, Goal::AnyEdge);
+        game.runner.position = (0, 4);
+        game.blocker.blocked_squares.insert((1, 4));
+        assert_eq!(game.check_runner_move((-1, 4)), Err(MoveError::OutOfBounds));
+        assert_eq!(game.check_runner_move((2, 4)), Err(MoveError::OutOfReach));
+        assert_eq!(game.check_runner_move((1, 4)), Err(MoveError::Blocked));
+        assert_eq!(game.check_runner_move((1, 5)), Ok(()));
+        assert_eq!(
+            game.update("move_runner 3 4", Role::Runner),
+            Err(GameError::IllegalMove(MoveError::OutOfReach))
+        );
+        assert_eq!(game.runner.position, (0, 4));
+    }
+
+    #[test]
+    fn malformed_commands_are_rejected_without_a_panic() {
+        let mut game = playing(9, 1, Goal::AnyEdge);
+        for command in ["", "   ", "move_runner", "move_runner 4", "move_runner a b", "move_runner 4 3 2", "move_runner 4 99999"] {
+            assert_eq!(
+                game.update(command, Role::Runner),
+                Err(GameError::MalformedCommand(command.to_string())),
+                "{:?}",
+                command
+            );
+        }
+        assert_eq!(game.update("teleport 4 3", Role::Runner), Err(GameError::UnknownCommand(String::from("teleport"))));
+        assert_eq!(game.update("move_blocker 4 3", Role::Runner), Err(GameError::NotYourTurn));
+        assert!(game.moves.is_empty());
+        assert_eq!(game.update("move_runner 4 3", Role::Runner), Ok(()));
+    }
+
+    #[test]
+    fn blocks_are_checked_for_bounds_the_runner_and_repeats() {
+        let mut game = playing(9, 1, Goal::AnyEdge);
+        game.play(Role::Runner, (4, 3)).unwrap();
+        assert_eq!(game.check_block((9, 0)), Err(MoveError::OutOfBounds));
+        assert_eq!(game.check_block((4, 3)), Err(MoveError::RunnerSquare));
+        assert_eq!(game.play(Role::Blocker, (4, 3)), Err(GameError::IllegalMove(MoveError::RunnerSquare)));
+        game.play(Role::Blocker, (0, 0)).unwrap();
+        game.play(Role::Runner, (4, 4)).unwrap();
+        assert_eq!(game.check_block((0, 0)), Err(MoveError::Blocked));
+    }
+
+    #[test]
+    fn runner_boxed_in_on_its_own_square_is_trapped() {
+        let mut game = playing(9, 1, Goal::AnyEdge);
+        assert_eq!(game.check_runner_move((4, 4)), Err(MoveError::OutOfReach));
+        game.current_player = Role::Blocker;
+        let neighbours: Vec<Point> = (3..=5)
+            .flat_map(|x| (3..=5).map(move |y| (x, y)))
+            .filter(|&pos| pos != (4, 4))
+            .collect();
+        game.blocker.blocked_squares.extend(&neighbours[1..]);
+        assert!(!game.runner_trapped());
+        game.play(Role::Blocker, neighbours[0]).unwrap();
+        assert!(game.runner_moves_from((4, 4)).is_empty());
+        assert!(game.game_over);
+        assert_eq!(game.game_state, GameState::GameOver(false));
+    }
+
+    #[test]
+    fn sealing_the_runner_in_ends_the_game_only_when_asked() {
+        let ring: Vec<Point> = (2..=6)
+            .flat_map(|x| (2..=6).map(move |y| (x, y)))
+            .filter(|&(x, y)| x == 2 || x == 6 || y == 2 || y == 6)
+            .collect();
+        for end_when_sealed in [false, true] {
+            let mut game = playing(9, 1, Goal::AnyEdge);
+            game.end_when_sealed = end_when_sealed;
+            game.current_player = Role::Blocker;
+            game.blocker.blocked_squares.extend(&ring[1..]);
+            assert!(game.goal_reachable());
+            game.play(Role::Blocker, ring[0]).unwrap();
+            assert!(game.runner_can_move());
+            assert!(!game.goal_reachable());
+            assert_eq!(game.is_over(), end_when_sealed);
+        }
+    }
+
+    #[test]
+    fn undo_restores_turns_flags_and_the_player_to_move() {
+        let mut game = playing(9, 1, Goal::AnyEdge);
+        game.play(Role::Runner, (4, 3)).unwrap();
+        game.play(Role::Blocker, (0, 0)).unwrap();
+        game.play(Role::Runner, (4, 2)).unwrap();
+        assert_eq!(game.turn_count, 1);
+
+        assert_eq!(game.undo(), Some(Move { role: Role::Runner, pos: (4, 2) }));
+        assert_eq!((game.turn_count, game.current_player), (1, Role::Runner));
+        assert!(!game.runner.moved && !game.blocker.moved);
+        assert_eq!(game.runner.position, (4, 3));
+
+        assert_eq!(game.undo(), Some(Move { role: Role::Blocker, pos: (0, 0) }));
+        assert_eq!((game.turn_count, game.current_player), (0, Role::Blocker));
+        assert!(game.runner.moved && !game.blocker.moved);
+        assert!(!game.is_blocked((0, 0)));
+
+        game.undo();
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.runner.position, (4, 4));
+        assert_eq!(game.game_state, GameState::Playing);
+    }
+
+    #[test]
+    fn undoing_a_winning_move_reopens_the_game() {
+        let mut game = playing(5, 1, Goal::AnyEdge);
+        game.play(Role::Runner, (1, 2)).unwrap();
+        game.play(Role::Blocker, (4, 4)).unwrap();
+        game.play(Role::Runner, (0, 2)).unwrap();
+        assert!(game.won);
+        game.undo();
+        assert!(!game.is_over());
+        assert_eq!(game.game_state, GameState::Playing);
+        assert_eq!((game.runner.position, game.current_player), ((1, 2), Role::Runner));
+    }
+
+    #[test]
+    fn take_back_rewinds_to_before_the_players_last_move() {
+        let mut game = playing(9, 1, Goal::AnyEdge);
+        game.play(Role::Runner, (4, 3)).unwrap();
+        game.play(Role::Blocker, (0, 0)).unwrap();
+        game.play(Role::Runner, (4, 2)).unwrap();
+
+        assert!(game.take_back(Role::Blocker));
+        assert_eq!(game.moves, vec![Move { role: Role::Runner, pos: (4, 3) }]);
+        assert_eq!((game.turn_count, game.current_player), (0, Role::Blocker));
+        assert!(game.runner.moved && !game.blocker.moved);
+        assert_eq!(game.runner.position, (4, 3));
+        assert!(!game.is_blocked((0, 0)));
+
+        assert!(!game.take_back(Role::Blocker));
+        assert_eq!(game.moves.len(), 1);
+        assert!(game.take_back(Role::Runner));
+        assert_eq!((game.turn_count, game.current_player), (0, Role::Runner));
+        assert!(!game.runner.moved && game.moves.is_empty());
+    }
+
+    #[test]
+    fn runner_trapped_before_the_first_move_loses_once_in_play() {
+        let mut game = playing(9, 1, Goal::AnyEdge);
+        for x in 3..=5 {
+            for y in 3..=5 {
+                if (x, y) != (4, 4) {
+                    game.blocker.blocked_squares.insert((x, y));
+                }
+            }
+        }
+        assert!(!game.is_over());
+        game.end_if_trapped();
+        assert!(game.game_over);
+        assert_eq!(game.game_state, GameState::GameOver(false));
+    }
+
+    #[test]
+    fn movement_rules_reach_the_right_squares() {
+        let from = (10, 10);
+        assert!(MovementRule::Manhattan.reaches(from, (12, 11), 3));
+        assert!(!MovementRule::Manhattan.reaches(from, (12, 12), 3));
+        assert!(MovementRule::Euclidean.reaches(from, (13, 14), 5));
+        assert!(!MovementRule::Euclidean.reaches(from, (14, 14), 5));
+        assert!(MovementRule::Chebyshev.reaches(from, (15, 15), 5));
+        for rule in [MovementRule::Chebyshev, MovementRule::Manhattan, MovementRule::Euclidean, MovementRule::Fool] {
+            assert!(!rule.reaches(from, from, 5), "{}", rule);
+        }
+
+        let knight = MovementRule::Leaper(2, 1);
+        for (dx, dy) in [(2, 1), (1, 2), (-2, 1), (-1, -2), (2, -1)] {
+            assert!(knight.reaches(from, (10 + dx, 10 + dy), 1));
+            assert!(knight.reaches((10 + dx, 10 + dy), from, 1));
+        }
+        assert!(!knight.reaches(from, (11, 11), 1));
+        assert!(!knight.reaches(from, (12, 12), 1));
+
+        assert!(MovementRule::Fool.reaches(from, (9, 9), 1));
+        assert!(MovementRule::Fool.reaches(from, (11, 9), 1));
+        assert!(!MovementRule::Fool.reaches(from, (11, 10), 1));
+        assert!(!MovementRule::Fool.reaches(from, (10, 11), 1));
+        assert!(!MovementRule::Fool.reaches(from, (10, 8), 1));
+    }
+
+    #[test]
+    fn movement_rules_parse_what_they_print() {
+        let rules = [
+            MovementRule::Chebyshev,
+            MovementRule::Manhattan,
+            MovementRule::Euclidean,
+            MovementRule::Leaper(3, 0),
+            MovementRule::Fool,
+        ];
+        for rule in rules {
+            assert_eq!(rule.to_string().parse(), Ok(rule));
+        }
+        assert_eq!("knight".parse(), Ok(MovementRule::Leaper(2, 1)));
+        assert_eq!("leaper: 1 , 4".parse(), Ok(MovementRule::Leaper(1, 4)));
+
+        assert!(!MovementRule::Leaper(0, 0).is_valid());
+        assert!(!MovementRule::Leaper(MAX_POWER + 1, 1).is_valid());
+        assert!(!MovementRule::Leaper(-1, 2).is_valid());
+        for bad in ["leaper:0,0", "leaper:1", "leaper:a,b", "leaper:1,200", "king", ""] {
+            assert!(bad.parse::<MovementRule>().is_err(), "{:?}", bad);
+        }
+    }
+}
@@ -1,126 +1,312 @@
 use macroquad::prelude::*;
+use macroquad::ui::root_ui;
+use std::path::Path;
+use ::rand::Rng;
+use angel::agent::{self, Agent, CpuAgent};
+use angel::ai::Difficulty;
+use angel::engine::EngineAgent;
+use angel::save::SavedGame;
+use angel::setup::{self, Pattern, Setup};
+use angel::{Game, GameState, Goal, Move, MovementRule, Point, Role, MAX_POWER};
 
+const USAGE: &str = "usage: A8 [--runner human|easy|medium|hard] [--blocker human|easy|medium|hard] \
+[--runner-engine COMMAND] [--blocker-engine COMMAND] [--power POWER] [--movement RULE] [--blocks COUNT | --density FRACTION] \
+[--pattern uniform|symmetric|clustered|empty] [--seed SEED] [--unbounded] [--turn-limit TURNS] [--end-when-sealed]";
+const SAVE_PATH: &str = "angel-save.json";
 const SQUARES: i16 = 16;
+/// Pause between CPU moves so CPU-vs-CPU games can be followed.
+const CPU_DELAY: f64 = 0.3;
 
-type Point = (i16, i16);
-
-struct Angel {
-    position: Point,
+struct Options {
     power: i16,
-    moved: bool,
+    rule: MovementRule,
+    pattern: Pattern,
+    blocks: usize,
+    /// Seed of the first game; each new game uses the next one.
+    seed: u64,
+    goal: Goal,
+    turn_limit: Option<i32>,
+    end_when_sealed: bool,
 }
 
-struct Devil {
-    blocked_squares: Vec<Point>,
-    moved: bool,
+fn new_game(options: &Options, seed: u64) -> Game {
+    let mut game = Game::new(SQUARES, options.power, options.goal);
+    game.end_when_sealed = options.end_when_sealed;
+    game.turn_limit = options.turn_limit;
+    game.rule = options.rule;
+    Setup {
+        pattern: options.pattern,
+        blocks: options.blocks,
+        margin: 0,
+        seed,
+    }
+    .apply(&mut game);
+    if !game.is_over() {
+        game.game_state = GameState::Playing;
+    }
+    game
 }
 
-fn get_grid_pos(mouse_pos: Vec2, offset_x: f32, offset_y: f32, sq_size: f32) -> Option<Point> {
+fn get_grid_pos(mouse_pos: Vec2, offset_x: f32, offset_y: f32, sq_size: f32, squares: i16, origin: Point) -> Option<Point> {
     let grid_x = ((mouse_pos.x - offset_x) / sq_size) as i16;
     let grid_y = ((mouse_pos.y - offset_y) / sq_size) as i16;
 
-    if grid_x >= 0 && grid_x < SQUARES && grid_y >= 0 && grid_y < SQUARES {
-        Some((grid_x, grid_y))
+    if (0..squares).contains(&grid_x) && (0..squares).contains(&grid_y) {
+        Some((origin.0 + grid_x, origin.1 + grid_y))
     } else {
         None
     }
 }
 
-fn is_within_power(start: Point, end: Point, power: i16) -> bool {
-    let dx = (start.0 - end.0).abs();
-    let dy = (start.1 - end.1).abs();
-    dx <= power && dy <= power && !(dx == 0 && dy == 0)
+/// Takes back one move, or back to the start of the turn if `full_turn`,
+/// keeping what was taken back on `stack`.
+fn undo(game: &mut Game, stack: &mut Vec<Move>, full_turn: bool) {
+    while let Some(m) = game.undo() {
+        stack.push(m);
+        if !full_turn || game.current_player == Role::Runner {
+            break;
+        }
+    }
 }
 
-#[macroquad::main("Angel Problem")]
-async fn main() {
-    let mut angel = Angel {
-        position: (SQUARES/2, SQUARES-1),
-        power: 2,
-        moved: false,
-    };
+fn redo(game: &mut Game, stack: &mut Vec<Move>, full_turn: bool) {
+    while let Some(m) = stack.pop() {
+        if game.play(m.role, m.pos).is_err() {
+            stack.clear();
+            break;
+        }
+        if !full_turn || game.current_player == Role::Runner {
+            break;
+        }
+    }
+}
+
+fn hovered_square(game: &Game) -> Option<Point> {
+    let game_size = screen_width().min(screen_height());
+    let offset_x = (screen_width() - game_size) / 2. + 10.;
+    let offset_y = (screen_height() - game_size) / 2. + 10.;
+    let sq_size = (screen_height() - offset_y * 2.) / game.squares as f32;
+    let mouse_pos = mouse_position();
+    get_grid_pos(Vec2::new(mouse_pos.0, mouse_pos.1), offset_x, offset_y, sq_size, game.squares, game.origin())
+}
+
+/// Whoever sits at this computer: plays the square they click.
+struct MouseAgent;
+
+impl Agent for MouseAgent {
+    fn choose_move(&mut self, game: &Game) -> Option<Point> {
+        if !is_mouse_button_pressed(MouseButton::Left) {
+            return None;
+        }
+        hovered_square(game).filter(|pos| match game.current_player {
+            Role::Runner => game.is_valid_runner_move(*pos),
+            Role::Blocker => game.is_valid_block(*pos),
+        })
+    }
+}
+
+struct Player {
+    agent: Box<dyn Agent>,
+    human: bool,
+}
 
-    let mut devil = Devil {
-        blocked_squares: Vec::new(),
-        moved: false,
+fn human() -> Player {
+    Player {
+        agent: Box::new(MouseAgent),
+        human: true,
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {:?} for {}", value, flag))
+}
+
+fn parse_player(flag: &str, value: Option<String>) -> Result<Player, String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", flag))?;
+    if value == "human" {
+        return Ok(human());
+    }
+    let difficulty: Difficulty = value.parse()?;
+    Ok(Player {
+        agent: Box::new(CpuAgent::new(difficulty)),
+        human: false,
+    })
+}
+
+fn parse_engine(flag: &str, value: Option<String>) -> Result<Player, String> {
+    let command = value.ok_or_else(|| format!("missing value for {}", flag))?;
+    let engine = EngineAgent::spawn(&command).map_err(|e| format!("could not start engine {:?}: {}", command, e))?;
+    Ok(Player {
+        agent: Box::new(engine),
+        human: false,
+    })
+}
+
+fn parse_args() -> Result<(Player, Player, Options), String> {
+    let mut runner = human();
+    let mut blocker = human();
+    let mut options = Options {
+        power: 2,
+        rule: MovementRule::Chebyshev,
+        pattern: Pattern::Uniform,
+        blocks: SQUARES as usize / 2,
+        seed: ::rand::thread_rng().gen(),
+        goal: Goal::TopRow,
+        turn_limit: None,
+        end_when_sealed: false,
     };
 
-    let mut rng = rand::gen_range(0, SQUARES);
-    rng = rand::gen_range(0, SQUARES);
-    for _ in 0..rng {
-        devil.blocked_squares.push((rand::gen_range(0, SQUARES), rand::gen_range(0, SQUARES)));
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--runner" => runner = parse_player(&flag, args.next())?,
+            "--blocker" => blocker = parse_player(&flag, args.next())?,
+            "--runner-engine" => runner = parse_engine(&flag, args.next())?,
+            "--blocker-engine" => blocker = parse_engine(&flag, args.next())?,
+            "--power" => {
+                let power: i16 = parse_value(&flag, args.next())?;
+                if !(1..=MAX_POWER).contains(&power) {
+                    return Err(format!("--power must be between 1 and {}", MAX_POWER));
+                }
+                options.power = power;
+            }
+            "--movement" => options.rule = parse_value(&flag, args.next())?,
+            "--blocks" => options.blocks = parse_value(&flag, args.next())?,
+            "--density" => {
+                let density: f64 = parse_value(&flag, args.next())?;
+                if !(0. ..=1.).contains(&density) {
+                    return Err(String::from("--density must be between 0 and 1"));
+                }
+                options.blocks = setup::blocks_for_density(SQUARES, density);
+            }
+            "--pattern" => options.pattern = parse_value(&flag, args.next())?,
+            "--seed" => options.seed = parse_value(&flag, args.next())?,
+            "--unbounded" => options.goal = Goal::Survive,
+            "--turn-limit" => {
+                let limit: i32 = parse_value(&flag, args.next())?;
+                if limit < 1 {
+                    return Err(String::from("--turn-limit must be at least 1"));
+                }
+                options.turn_limit = Some(limit);
+            }
+            "--end-when-sealed" => options.end_when_sealed = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(format!("unknown argument {:?}", flag)),
+        }
     }
+    Ok((runner, blocker, options))
+}
 
-    let mut game_over = false;
-    let mut won = false;
-    let mut turn_count = 0;
-    let mut hover_pos: Option<Point> = None;
+#[macroquad::main("Angel Problem")]
+async fn main() {
+    let (mut runner, mut blocker, options) = match parse_args() {
+        Ok(players) => players,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let mut seed = options.seed;
+    let mut game = new_game(&options, seed);
+    // `None` once a saved game replaces the generated one.
+    let mut shown_seed = Some(seed);
+    let mut last_move = get_time();
+    // The ply being shown while stepping through a finished game.
+    let mut replay: Option<usize> = None;
+    let mut redo_stack: Vec<Move> = Vec::new();
 
     loop {
-        if !game_over && !won {
-            let game_size = screen_width().min(screen_height());
-            let offset_x = (screen_width() - game_size) / 2. + 10.;
-            let offset_y = (screen_height() - game_size) / 2. + 10.;
-            let sq_size = (screen_height() - offset_y * 2.) / SQUARES as f32;
-            let mouse_pos = mouse_position();
-            hover_pos = get_grid_pos(Vec2::new(mouse_pos.0, mouse_pos.1), offset_x, offset_y, sq_size);
-
-            if is_mouse_button_pressed(MouseButton::Left) {
-                if let Some(grid_pos) = hover_pos {
-                    if !angel.moved {
-                        if is_within_power(angel.position, grid_pos, angel.power)
-                            && !devil.blocked_squares.contains(&grid_pos) {
-                            angel.position = grid_pos;
-                            angel.moved = true;
-
-                            if angel.position.1 == 0 {
-                                won = true;
-                            }
-                        }
-                    } else if !devil.moved {
-                        if grid_pos != angel.position && !devil.blocked_squares.contains(&grid_pos) {
-                            devil.blocked_squares.push(grid_pos);
-                            devil.moved = true;
-
-                            let mut can_move = false;
-                            'check: for dx in -angel.power..=angel.power {
-                                for dy in -angel.power..=angel.power {
-                                    let test_pos = (
-                                        angel.position.0 + dx,
-                                        angel.position.1 + dy
-                                    );
-                                    if test_pos.0 >= 0 && test_pos.0 < SQUARES &&
-                                        test_pos.1 >= 0 && test_pos.1 < SQUARES &&
-                                        !devil.blocked_squares.contains(&test_pos) {
-                                        can_move = true;
-                                        break 'check;
-                                    }
-                                }
-                            }
-                            if !can_move {
-                                game_over = true;
-                            }
-                        }
-                    }
+        let player = match game.current_player {
+            Role::Runner => &mut runner,
+            Role::Blocker => &mut blocker,
+        };
+        let hover_pos = if player.human && replay.is_none() {
+            hovered_square(&game)
+        } else {
+            None
+        };
+        if replay.is_none() && !game.is_over() && (player.human || get_time() - last_move >= CPU_DELAY) {
+            match agent::step(&mut game, player.agent.as_mut()) {
+                Ok(true) => {
+                    last_move = get_time();
+                    redo_stack.clear();
                 }
+                Ok(false) => {}
+                Err(e) => println!("Move rejected: {}", e),
             }
+        }
+
+        if replay.is_none() {
+            let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+            let buttons = [
+                root_ui().button(vec2(screen_width() - 100., 20.), "Undo turn"),
+                root_ui().button(vec2(screen_width() - 100., 45.), "Undo move"),
+                root_ui().button(vec2(screen_width() - 100., 70.), "Redo move"),
+                root_ui().button(vec2(screen_width() - 100., 95.), "Redo turn"),
+            ];
+            if buttons[0] || buttons[1] || is_key_pressed(KeyCode::Z) {
+                undo(&mut game, &mut redo_stack, buttons[0] || (shift && !buttons[1]));
+                last_move = get_time();
+            } else if buttons[2] || buttons[3] || is_key_pressed(KeyCode::Y) {
+                redo(&mut game, &mut redo_stack, buttons[3] || (shift && !buttons[2]));
+                last_move = get_time();
+            }
+        }
 
-            if angel.moved && devil.moved {
-                angel.moved = false;
-                devil.moved = false;
-                turn_count += 1;
+        let plies = game.moves.len();
+        let scrubber = Rect::new(10., screen_height() - 50., screen_width() - 20., 12.);
+        if let Some(ply) = &mut replay {
+            if is_key_pressed(KeyCode::Left) {
+                *ply = ply.saturating_sub(1);
+            } else if is_key_pressed(KeyCode::Right) {
+                *ply = (*ply + 1).min(plies);
+            } else if is_key_pressed(KeyCode::Home) {
+                *ply = 0;
+            } else if is_key_pressed(KeyCode::End) {
+                *ply = plies;
             }
+            let mouse_pos = mouse_position();
+            let grab = Rect::new(scrubber.x, scrubber.y - 10., scrubber.w, scrubber.h + 20.);
+            if is_mouse_button_down(MouseButton::Left) && grab.contains(Vec2::new(mouse_pos.0, mouse_pos.1)) {
+                let fraction = (mouse_pos.0 - scrubber.x) / scrubber.w;
+                *ply = (fraction * plies as f32).round().clamp(0., plies as f32) as usize;
+            }
+            if is_key_pressed(KeyCode::Escape) {
+                replay = None;
+            }
+        } else if game.is_over() && is_key_pressed(KeyCode::P) {
+            replay = Some(plies);
         }
 
+        let replayed;
+        let shown = match replay {
+            Some(ply) => {
+                replayed = game.position_at(ply);
+                &replayed
+            }
+            None => &game,
+        };
+
         clear_background(LIGHTGRAY);
 
         let game_size = screen_width().min(screen_height());
         let offset_x = (screen_width() - game_size) / 2. + 10.;
         let offset_y = (screen_height() - game_size) / 2. + 10.;
-        let sq_size = (screen_height() - offset_y * 2.) / SQUARES as f32;
+        let sq_size = (screen_height() - offset_y * 2.) / shown.squares as f32;
+        // Where square (0, 0) is drawn; on an unbounded board the view
+        // follows the angel.
+        let origin = shown.origin();
+        let board_x = offset_x - origin.0 as f32 * sq_size;
+        let board_y = offset_y - origin.1 as f32 * sq_size;
 
         draw_rectangle(offset_x, offset_y, game_size - 20., game_size - 20., WHITE);
-        for i in 1..SQUARES {
+        for i in 1..shown.squares {
             draw_line(
                 offset_x,
                 offset_y + sq_size * i as f32,
@@ -139,20 +325,22 @@ async fn main() {
             );
         }
 
-        for i in 0..SQUARES {
-            draw_rectangle(
-                offset_x + i as f32 * sq_size,
-                offset_y,
-                sq_size,
-                sq_size,
-                SKYBLUE,
-            );
+        for (x, y) in shown.area() {
+            if shown.is_goal((x, y)) {
+                draw_rectangle(
+                    board_x + x as f32 * sq_size,
+                    board_y + y as f32 * sq_size,
+                    sq_size,
+                    sq_size,
+                    SKYBLUE,
+                );
+            }
         }
 
-        for pos in &devil.blocked_squares {
+        for pos in shown.blocker.blocked_squares.iter().filter(|pos| shown.in_area(**pos)) {
             draw_rectangle(
-                offset_x + pos.0 as f32 * sq_size,
-                offset_y + pos.1 as f32 * sq_size,
+                board_x + pos.0 as f32 * sq_size,
+                board_y + pos.1 as f32 * sq_size,
                 sq_size,
                 sq_size,
                 RED,
@@ -160,55 +348,87 @@ async fn main() {
         }
 
         draw_rectangle(
-            offset_x + angel.position.0 as f32 * sq_size,
-            offset_y + angel.position.1 as f32 * sq_size,
+            board_x + shown.runner.position.0 as f32 * sq_size,
+            board_y + shown.runner.position.1 as f32 * sq_size,
             sq_size,
             sq_size,
             GOLD,
         );
 
-        if let Some(pos) = hover_pos {
-            if !angel.moved {
-                if is_within_power(angel.position, pos, angel.power)
-                    && !devil.blocked_squares.contains(&pos) {
-                    draw_rectangle(
-                        offset_x + pos.0 as f32 * sq_size,
-                        offset_y + pos.1 as f32 * sq_size,
-                        sq_size,
-                        sq_size,
-                        Color::new(0.0, 1.0, 0.0, 0.3),
-                    );
-                }
-            } else if !devil.moved {
-                if pos != angel.position && !devil.blocked_squares.contains(&pos) {
+        if let Some(pos) = hover_pos.filter(|_| !game.is_over()) {
+            if game.current_player == Role::Runner {
+                // Everywhere the angel can land this turn.
+                for (x, y) in game.runner_moves_in_area(game.runner.position) {
                     draw_rectangle(
-                        offset_x + pos.0 as f32 * sq_size,
-                        offset_y + pos.1 as f32 * sq_size,
+                        board_x + x as f32 * sq_size,
+                        board_y + y as f32 * sq_size,
                         sq_size,
                         sq_size,
-                        Color::new(1.0, 0.0, 0.0, 0.3),
+                        Color::new(0.0, 1.0, 0.0, 0.12),
                     );
                 }
             }
+            let (color, valid_move) = match game.current_player {
+                Role::Runner => (Color::new(0.0, 1.0, 0.0, 0.3), game.is_valid_runner_move(pos)),
+                Role::Blocker => (Color::new(1.0, 0.0, 0.0, 0.3), game.is_valid_block(pos)),
+            };
+            if valid_move {
+                draw_rectangle(
+                    board_x + pos.0 as f32 * sq_size,
+                    board_y + pos.1 as f32 * sq_size,
+                    sq_size,
+                    sq_size,
+                    color,
+                );
+            }
         }
 
-        draw_text(format!("TURN: {turn_count}").as_str(), 10., 45., 20., DARKGRAY);
+        if let Some(seed) = shown_seed {
+            draw_text(format!("SEED: {}", seed).as_str(), 10., 20., 20., DARKGRAY);
+        }
+        let turn = match shown.turn_limit {
+            Some(limit) => format!("TURN: {}/{}", shown.turn_count, limit),
+            None => format!("TURN: {}", shown.turn_count),
+        };
+        let turn = format!("{} | POWER: {} | MOVES: {}", turn, shown.power, shown.rule);
+        draw_text(turn.as_str(), 10., 45., 20., DARKGRAY);
 
-        let turn_text = if !angel.moved {
-            "Angel's Turn"
-        } else if !devil.moved {
-            "Devil's Turn"
-        } else {
-            "Processing..."
+        let turn_text = match shown.current_player {
+            Role::Runner => "Angel's Turn",
+            Role::Blocker => "Devil's Turn",
         };
         draw_text(turn_text, 10., 70., 20., DARKGRAY);
 
-        if game_over || won {
-            let text = if won {
-                "Press [enter] to play again."
-            } else {
-                "Press [enter] to play again."
+        if let Some(ply) = replay {
+            let last = ply.checked_sub(1).map(|i| game.moves[i]);
+            let text = match last {
+                Some(m) => {
+                    if shown.in_area(m.pos) {
+                        draw_rectangle_lines(
+                            board_x + m.pos.0 as f32 * sq_size,
+                            board_y + m.pos.1 as f32 * sq_size,
+                            sq_size,
+                            sq_size,
+                            4.,
+                            DARKPURPLE,
+                        );
+                    }
+                    match m.role {
+                        Role::Runner => format!("Ply {}/{}: the angel moved to ({}, {})", ply, plies, m.pos.0, m.pos.1),
+                        Role::Blocker => format!("Ply {}/{}: the devil blocked ({}, {})", ply, plies, m.pos.0, m.pos.1),
+                    }
+                }
+                None => format!("Ply 0/{}: starting position", plies),
             };
+            draw_text(text.as_str(), 10., 95., 20., DARKGRAY);
+            draw_text("[left]/[right] step, [home]/[end] jump, [esc] leave replay", 10., 120., 20., DARKGRAY);
+
+            draw_rectangle(scrubber.x, scrubber.y, scrubber.w, scrubber.h, GRAY);
+            let fraction = if plies == 0 { 1. } else { ply as f32 / plies as f32 };
+            draw_rectangle(scrubber.x, scrubber.y, scrubber.w * fraction, scrubber.h, DARKPURPLE);
+            draw_circle(scrubber.x + scrubber.w * fraction, scrubber.y + scrubber.h / 2., 9., DARKPURPLE);
+        } else if game.is_over() {
+            let text = "Press [enter] to play again, [P] to replay.";
             let font_size = 30.;
             let text_size = measure_text(text, None, font_size as _, 1.0);
 
@@ -217,29 +437,42 @@ async fn main() {
                 screen_width() / 2. - text_size.width / 2.,
                 screen_height() / 2. + text_size.height / 2.,
                 font_size,
-                if won { SKYBLUE } else { DARKGRAY },
+                if game.won { SKYBLUE } else { DARKGRAY },
             );
 
             if is_key_down(KeyCode::Enter) {
-                angel = Angel {
-                    position: (SQUARES/2, SQUARES-1),
-                    power: 2,
-                    moved: false,
-                };
-                devil = Devil {
-                    blocked_squares: Vec::new(),
-                    moved: false,
-                };
-                turn_count = 0;
-                game_over = false;
-                won = false;
-                rng = rand::gen_range(0, SQUARES);
-                for _ in 0..rng {
-                    devil.blocked_squares.push((rand::gen_range(0, SQUARES), rand::gen_range(0, SQUARES)));
+                seed = seed.wrapping_add(1);
+                game = new_game(&options, seed);
+                shown_seed = Some(seed);
+                redo_stack.clear();
+            }
+        }
+
+        if is_key_pressed(KeyCode::S) {
+            match SavedGame::from_game(&game).write(Path::new(SAVE_PATH)) {
+                Ok(()) => println!("Saved the game to {}", SAVE_PATH),
+                Err(e) => println!("Failed to save the game: {}", e),
+            }
+        }
+        if is_key_pressed(KeyCode::L) {
+            match SavedGame::read(Path::new(SAVE_PATH)).and_then(|save| save.restore().map_err(std::io::Error::other)) {
+                Ok(loaded) => {
+                    game = loaded;
+                    shown_seed = None;
+                    replay = None;
+                    redo_stack.clear();
                 }
+                Err(e) => println!("Failed to load {}: {}", SAVE_PATH, e),
             }
         }
+        draw_text(
+            "[S] save the game, [L] load the last save, [Z]/[Y] undo/redo a move, with [shift] a turn",
+            10.,
+            screen_height() - 10.,
+            16.,
+            DARKGRAY,
+        );
 
         next_frame().await;
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,100 @@
+//! Length-delimited JSON frames shared by the client and the server.
+//!
+//! Every message is a big-endian `u32` byte length followed by that many
+//! bytes of JSON, so a reader never has to guess where one message ends.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, Read, Write};
+
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+pub fn write_frame<W: Write, T: Serialize>(writer: &mut W, message: &T) -> io::Result<()> {
+    let payload = serde_json::to_vec(message)?;
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::FileTooLarge, "frame too large"));
+    }
+
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+pub fn read_frame<R: Read, T: DeserializeOwned>(reader: &mut R) -> io::Result<T> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::FileTooLarge, "frame too large"));
+    }
+
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ClientMessage;
+    use std::io::Cursor;
+
+    /// Hands out at most one byte per read, like a socket that splits frames.
+    struct Trickle<R>(R);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    fn frames(messages: &[ClientMessage]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for message in messages {
+            write_frame(&mut bytes, message).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_a_frame_split_across_reads() {
+        let mut reader = Trickle(Cursor::new(frames(&[ClientMessage::Move { x: 3, y: -4 }])));
+        let message: ClientMessage = read_frame(&mut reader).unwrap();
+        assert!(matches!(message, ClientMessage::Move { x: 3, y: -4 }));
+    }
+
+    #[test]
+    fn reads_back_to_back_frames_one_at_a_time() {
+        let mut reader = Cursor::new(frames(&[
+            ClientMessage::Move { x: 1, y: 2 },
+            ClientMessage::Resign,
+            ClientMessage::Heartbeat,
+        ]));
+        assert!(matches!(read_frame(&mut reader).unwrap(), ClientMessage::Move { x: 1, y: 2 }));
+        assert!(matches!(read_frame(&mut reader).unwrap(), ClientMessage::Resign));
+        assert!(matches!(read_frame(&mut reader).unwrap(), ClientMessage::Heartbeat));
+        let end = read_frame::<_, ClientMessage>(&mut reader).unwrap_err();
+        assert_eq!(end.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn refuses_an_oversized_length_without_reading_it() {
+        let mut bytes = (MAX_FRAME_LEN as u32 + 1).to_be_bytes().to_vec();
+        bytes.extend_from_slice(b"{}");
+        let mut reader = Cursor::new(bytes);
+        let error = read_frame::<_, ClientMessage>(&mut reader).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::FileTooLarge);
+        assert_eq!(reader.position(), 4);
+    }
+
+    #[test]
+    fn malformed_json_is_invalid_data() {
+        let mut bytes = 5u32.to_be_bytes().to_vec();
+        bytes.extend_from_slice(b"{oops");
+        let error = read_frame::<_, ClientMessage>(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
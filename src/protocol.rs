@@ -0,0 +1,106 @@
+//! Messages exchanged between the server and its clients.
+//!
+//! Each message is sent as one frame (see [`crate::net`]) containing a JSON
+//! object whose `"type"` field names the variant, e.g.
+//! `{"type":"Move","x":3,"y":4}` or `{"type":"StateUpdate","game":{...}}`.
+//! A client starts by sending `Join` with the protocol version it speaks and
+//! the name of the room to play in; the server answers with `Welcome` or
+//! `Refused`. `ListRooms` may be sent before joining. After a dropped
+//! connection, `Resume` with the token from `Welcome` takes the same seat back.
+
+use crate::ai::Difficulty;
+use crate::save::SavedGame;
+use crate::{Game, GameError, GameState, MovementRule, Role};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const PROTOCOL_VERSION: u32 = 15;
+
+/// How often a client should send `Heartbeat` while it has nothing else to say.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// A connection that sends nothing for this long is treated as dropped.
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type")]
+pub enum ClientMessage {
+    /// Handshake; must carry [`PROTOCOL_VERSION`]. The room is created if
+    /// it does not exist yet. `role` is a preference; if that seat is taken
+    /// the other one is assigned.
+    Join {
+        version: u32,
+        room: String,
+        role: Option<Role>,
+        /// The angel's power and movement rule in a room this creates; the
+        /// server's own settings if left out, and ignored when the room
+        /// already exists.
+        #[serde(default)]
+        power: Option<i16>,
+        #[serde(default)]
+        rule: Option<MovementRule>,
+    },
+    /// Reattach to the seat identified by a `Welcome` token, as long as the
+    /// seat has not been forfeited yet.
+    Resume { version: u32, token: String },
+    /// Ask for the rooms currently open on the server.
+    ListRooms,
+    /// Move the runner to, or block, the square `(x, y)` depending on the seat.
+    Move { x: i16, y: i16 },
+    /// Ask for the current state without doing anything.
+    Poll,
+    /// Start the game against the CPU instead of waiting for a second player,
+    /// playing `role`; answered with a fresh `Welcome`.
+    RequestCpu { role: Role, difficulty: Difficulty },
+    /// Give up; the opponent wins.
+    Resign,
+    /// Replace a finished game with a fresh one in the same room.
+    NewGame,
+    /// Ask the opponent to let this player take back their last move.
+    RequestTakeback,
+    /// Reply to a `TakebackRequested` from the opponent.
+    AnswerTakeback { accept: bool },
+    /// Replace a finished or not yet started game with a saved one.
+    Load { save: SavedGame },
+    /// Keep-alive; never answered. See [`HEARTBEAT_INTERVAL`].
+    Heartbeat,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoomSummary {
+    pub name: String,
+    pub players: usize,
+    pub state: GameState,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    /// Reply to `Join` and `Resume`: the seat this connection plays, the token
+    /// to resume it with, and the current state.
+    Welcome {
+        version: u32,
+        room: String,
+        role: Role,
+        token: String,
+        game: Game,
+    },
+    /// The state after the last accepted message.
+    StateUpdate { game: Game },
+    /// The last message was rejected; the state is unchanged.
+    Error { error: GameError },
+    /// The connection was not accepted (room full, version mismatch, ...).
+    Refused { reason: String },
+    /// Reply to `ListRooms`.
+    RoomList { rooms: Vec<RoomSummary> },
+    /// A player dropped; their seat is held for `grace_secs` seconds before
+    /// they forfeit.
+    PlayerLeft { role: Role, grace_secs: u64 },
+    /// A player who dropped took their seat back.
+    PlayerReturned { role: Role },
+    /// `role` wants to take back their last move; their opponent should
+    /// answer with `AnswerTakeback`.
+    TakebackRequested { role: Role },
+    /// The pending takeback was accepted (a `StateUpdate` with the rolled
+    /// back game follows) or declined.
+    TakebackAnswered { accepted: bool },
+}
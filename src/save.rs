@@ -0,0 +1,166 @@
+//! Saved games: the setup and the moves, as JSON. Loading replays the moves
+//! through the normal rules, so a file cannot produce an impossible position.
+
+use crate::{Game, GameState, Goal, Move, MovementRule, Point, Role, MAX_POWER, MAX_SQUARES};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Bumped whenever [`SavedGame`] changes shape.
+pub const SAVE_VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SavedGame {
+    pub version: u32,
+    pub squares: i16,
+    pub power: i16,
+    pub goal: Goal,
+    pub initial_blocks: Vec<Point>,
+    pub moves: Vec<Move>,
+    /// Whose turn it is once all moves are played.
+    pub current_player: Role,
+    pub resigned: Option<Role>,
+    /// Added in version 2.
+    #[serde(default)]
+    pub end_when_sealed: bool,
+    /// Added in version 3.
+    #[serde(default)]
+    pub turn_limit: Option<i32>,
+    /// Added in version 4.
+    #[serde(default)]
+    pub rule: MovementRule,
+}
+
+impl SavedGame {
+    pub fn from_game(game: &Game) -> Self {
+        SavedGame {
+            version: SAVE_VERSION,
+            squares: game.squares,
+            power: game.power,
+            goal: game.goal,
+            initial_blocks: game.initial_blocks().into_iter().collect(),
+            moves: game.moves.clone(),
+            current_player: game.current_player,
+            resigned: game.resigned,
+            end_when_sealed: game.end_when_sealed,
+            turn_limit: game.turn_limit,
+            rule: game.rule,
+        }
+    }
+
+    /// Rebuilds the game. It comes back `Playing`, or `GameOver` if it was
+    /// finished; callers waiting for players adjust the state themselves.
+    pub fn restore(&self) -> Result<Game, String> {
+        if self.version == 0 || self.version > SAVE_VERSION {
+            return Err(format!("save version {} is not supported, expected up to {}", self.version, SAVE_VERSION));
+        }
+        if !(3..=MAX_SQUARES).contains(&self.squares) || !(1..=MAX_POWER).contains(&self.power) {
+            return Err(format!("invalid board {}x{} with power {}", self.squares, self.squares, self.power));
+        }
+        if !self.rule.is_valid() {
+            return Err(format!("invalid movement rule {}", self.rule));
+        }
+
+        let mut game = Game::new(self.squares, self.power, self.goal);
+        game.game_state = GameState::Playing;
+        game.end_when_sealed = self.end_when_sealed;
+        game.turn_limit = self.turn_limit;
+        game.rule = self.rule;
+        for &pos in &self.initial_blocks {
+            if !game.in_bounds(pos) {
+                return Err(format!("initial block ({}, {}) is off the board", pos.0, pos.1));
+            }
+            if pos == game.runner.position {
+                return Err(format!("initial block ({}, {}) is on the runner's start", pos.0, pos.1));
+            }
+        }
+        game.blocker.blocked_squares = self.initial_blocks.iter().copied().collect();
+
+        for (i, m) in self.moves.iter().enumerate() {
+            game.play(m.role, m.pos)
+                .map_err(|e| format!("move {} ({} to ({}, {})): {}", i + 1, m.role, m.pos.0, m.pos.1, e))?;
+        }
+        if let Some(role) = self.resigned {
+            game.resign(role).map_err(|e| format!("{} resigned: {}", role, e))?;
+        }
+        if game.current_player != self.current_player {
+            return Err(format!("moves end on the {}'s turn, not the {}'s", game.current_player, self.current_player));
+        }
+        game.end_if_trapped();
+        if !game.is_over() && game.current_player == Role::Blocker && !game.blocker_can_move() {
+            return Err(String::from("the blocker has no square left to block"));
+        }
+        Ok(game)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Reads and checks a save file; the game itself comes from [`SavedGame::restore`].
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let saved: SavedGame = serde_json::from_slice(&fs::read(path)?)?;
+        saved
+            .restore()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(saved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game() -> Game {
+        let mut game = Game::new(9, 2, Goal::TopRow);
+        game.game_state = GameState::Playing;
+        game.end_when_sealed = true;
+        game.turn_limit = Some(40);
+        game.rule = MovementRule::Leaper(2, 1);
+        game.blocker.blocked_squares.extend([(0, 0), (8, 3)]);
+        game.play(Role::Runner, (5, 6)).unwrap();
+        game.play(Role::Blocker, (4, 4)).unwrap();
+        game.play(Role::Runner, (3, 5)).unwrap();
+        game
+    }
+
+    #[test]
+    fn round_trip_through_json_gives_back_the_same_game() {
+        let game = game();
+        let json = serde_json::to_string(&SavedGame::from_game(&game)).unwrap();
+        let restored = serde_json::from_str::<SavedGame>(&json).unwrap().restore().unwrap();
+
+        assert_eq!(restored.moves, game.moves);
+        assert_eq!(restored.blocker.blocked_squares, game.blocker.blocked_squares);
+        assert_eq!(restored.initial_blocks(), game.initial_blocks());
+        assert_eq!(restored.runner.position, (3, 5));
+        assert_eq!((restored.current_player, restored.turn_count), (Role::Blocker, 1));
+        assert_eq!((restored.squares, restored.power, restored.goal), (9, 2, Goal::TopRow));
+        assert_eq!((restored.end_when_sealed, restored.turn_limit, restored.rule), (true, Some(40), MovementRule::Leaper(2, 1)));
+        assert_eq!(restored.game_state, GameState::Playing);
+    }
+
+    #[test]
+    fn restore_refuses_oversized_boards_and_illegal_moves() {
+        let mut save = SavedGame::from_game(&game());
+        save.squares = MAX_SQUARES + 1;
+        assert!(save.restore().is_err());
+
+        let mut save = SavedGame::from_game(&game());
+        save.moves[2].pos = (3, 4);
+        assert!(save.restore().is_err());
+    }
+
+    #[test]
+    fn restore_refuses_a_block_on_the_start_and_ends_a_boxed_in_start() {
+        let mut save = SavedGame::from_game(&Game::new(9, 1, Goal::AnyEdge));
+        save.initial_blocks = (3..=5).flat_map(|x| (3..=5).map(move |y| (x, y))).collect();
+        assert!(save.restore().is_err());
+
+        save.initial_blocks.retain(|&pos| pos != (4, 4));
+        let game = save.restore().unwrap();
+        assert!(game.game_over);
+        assert_eq!(game.game_state, GameState::GameOver(false));
+    }
+}
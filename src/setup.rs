@@ -0,0 +1,241 @@
+//! Starting positions: where the random blocks go before the first move.
+
+use crate::{Game, Goal, Point};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Pattern {
+    /// Blocks anywhere, each square equally likely.
+    #[default]
+    Uniform,
+    /// Mirrored so that no way to the goal is favoured: four-fold around the
+    /// centre for [`Goal::AnyEdge`] and [`Goal::Survive`], left to right for
+    /// [`Goal::TopRow`]. The count is rounded down to whole mirror images.
+    Symmetric,
+    /// A few tight clumps.
+    Clustered,
+    /// No blocks at all.
+    Empty,
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(Pattern::Uniform),
+            "symmetric" => Ok(Pattern::Symmetric),
+            "clustered" => Ok(Pattern::Clustered),
+            "empty" => Ok(Pattern::Empty),
+            _ => Err(format!("unknown pattern {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Uniform => write!(f, "uniform"),
+            Pattern::Symmetric => write!(f, "symmetric"),
+            Pattern::Clustered => write!(f, "clustered"),
+            Pattern::Empty => write!(f, "empty"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Setup {
+    pub pattern: Pattern,
+    pub blocks: usize,
+    /// Squares kept clear along every edge.
+    pub margin: i16,
+    /// The same seed always gives the same position.
+    pub seed: u64,
+}
+
+/// How many blocks cover `density` (0 to 1) of a `squares` by `squares` board.
+pub fn blocks_for_density(squares: i16, density: f64) -> usize {
+    (density.clamp(0., 1.) * squares as f64 * squares as f64).round() as usize
+}
+
+impl Setup {
+    /// Places up to `blocks` distinct blocks in the area of a game that has
+    /// none yet, never on the runner's square, then lifts blocks until the
+    /// runner has a way to the goal under the game's movement rule, so set
+    /// that first. Should no way open up, the game ends then and there.
+    pub fn apply(&self, game: &mut Game) {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let (x, y) = game.origin();
+        let inner = self.margin..game.squares - self.margin;
+        let mut free: Vec<Point> = game
+            .area()
+            .filter(|&pos| inner.contains(&(pos.0 - x)) && inner.contains(&(pos.1 - y)) && pos != game.runner.position)
+            .collect();
+        let count = self.blocks.min(free.len());
+
+        let blocks = match self.pattern {
+            Pattern::Uniform => {
+                free.shuffle(&mut rng);
+                free.truncate(count);
+                free
+            }
+            Pattern::Symmetric => symmetric(game, &free, count, &mut rng),
+            Pattern::Clustered => clustered(&free, count, &mut rng),
+            Pattern::Empty => Vec::new(),
+        };
+        game.blocker.blocked_squares = blocks.into_iter().collect();
+        open_path(game, &mut rng);
+        game.end_if_trapped();
+    }
+}
+
+fn symmetric(game: &Game, free: &[Point], count: usize, rng: &mut StdRng) -> Vec<Point> {
+    let (ox, oy) = game.origin();
+    let last = game.squares - 1;
+    let images = |(x, y): Point| -> Vec<Point> {
+        let (x, y) = (x - ox, y - oy);
+        let images = match game.goal {
+            Goal::AnyEdge | Goal::Survive => vec![(x, y), (last - y, x), (last - x, last - y), (y, last - x)],
+            Goal::TopRow => vec![(x, y), (last - x, y)],
+        };
+        images.into_iter().map(|(x, y)| (x + ox, y + oy)).collect()
+    };
+
+    let allowed: HashSet<Point> = free.iter().copied().collect();
+    let mut order = free.to_vec();
+    order.shuffle(rng);
+    let mut placed: Vec<Point> = Vec::new();
+    for pos in order {
+        if placed.len() >= count {
+            break;
+        }
+        let mut orbit = images(pos);
+        orbit.sort();
+        orbit.dedup();
+        let fits = placed.len() + orbit.len() <= count;
+        if fits && orbit.iter().all(|image| allowed.contains(image) && !placed.contains(image)) {
+            placed.extend(orbit);
+        }
+    }
+    placed
+}
+
+fn clustered(free: &[Point], count: usize, rng: &mut StdRng) -> Vec<Point> {
+    let allowed: HashSet<Point> = free.iter().copied().collect();
+    let centers: Vec<Point> = free.choose_multiple(rng, count / 6 + 1).copied().collect();
+    let mut placed: HashSet<Point> = HashSet::new();
+    let mut blocks = Vec::new();
+    for _ in 0..count * 20 {
+        if blocks.len() >= count {
+            break;
+        }
+        let Some(&center) = centers.choose(rng) else { break };
+        let pos = (center.0 + rng.gen_range(-2..=2), center.1 + rng.gen_range(-2..=2));
+        if allowed.contains(&pos) && placed.insert(pos) {
+            blocks.push(pos);
+        }
+    }
+    blocks
+}
+
+/// Removes blocks on the edge of the runner's region until it reaches the goal.
+fn open_path(game: &mut Game, rng: &mut StdRng) {
+    while !game.goal_reachable() {
+        let mut reached = HashSet::from([game.runner.position]);
+        let mut queue = VecDeque::from([game.runner.position]);
+        while let Some(pos) = queue.pop_front() {
+            for step in game.runner_moves_in_area(pos) {
+                if reached.insert(step) {
+                    queue.push_back(step);
+                }
+            }
+        }
+
+        let frontier: Vec<Point> = game
+            .blocker
+            .blocked_squares
+            .iter()
+            .copied()
+            .filter(|&block| reached.iter().any(|&pos| game.rule.reaches(pos, block, game.runner.power)))
+            .collect();
+        let Some(block) = frontier.choose(rng) else { break };
+        game.blocker.blocked_squares.remove(block);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MovementRule;
+
+    #[test]
+    fn every_movement_rule_gets_a_way_to_the_goal() {
+        let rules = [
+            MovementRule::Chebyshev,
+            MovementRule::Manhattan,
+            MovementRule::Euclidean,
+            MovementRule::Leaper(2, 1),
+            MovementRule::Fool,
+        ];
+        for rule in rules {
+            for goal in [Goal::AnyEdge, Goal::TopRow, Goal::Survive] {
+                for seed in 0..20 {
+                    let mut game = Game::new(21, 1, goal);
+                    game.rule = rule;
+                    Setup { pattern: Pattern::Uniform, blocks: 160, margin: 1, seed }.apply(&mut game);
+                    assert!(game.goal_reachable(), "{} {:?} seed {}", rule, goal, seed);
+                    assert!(game.runner_can_move(), "{} {:?} seed {}", rule, goal, seed);
+                }
+            }
+        }
+    }
+
+    fn dealt(goal: Goal, pattern: Pattern, blocks: usize, seed: u64) -> Game {
+        let mut game = Game::new(21, 1, goal);
+        Setup { pattern, blocks, margin: 1, seed }.apply(&mut game);
+        game
+    }
+
+    #[test]
+    fn the_same_seed_deals_the_same_blocks() {
+        for pattern in [Pattern::Uniform, Pattern::Symmetric, Pattern::Clustered] {
+            let blocks = dealt(Goal::AnyEdge, pattern, 60, 5).blocker.blocked_squares;
+            assert_eq!(blocks, dealt(Goal::AnyEdge, pattern, 60, 5).blocker.blocked_squares);
+            assert_ne!(blocks, dealt(Goal::AnyEdge, pattern, 60, 6).blocker.blocked_squares);
+        }
+    }
+
+    #[test]
+    fn blocks_leave_the_start_and_the_margin_free_and_a_way_open() {
+        for pattern in [Pattern::Uniform, Pattern::Symmetric, Pattern::Clustered] {
+            for seed in 0..20 {
+                let game = dealt(Goal::AnyEdge, pattern, 200, seed);
+                assert!(!game.is_blocked(game.runner.position));
+                assert!(game.blocker.blocked_squares.iter().all(|&(x, y)| (1..20).contains(&x) && (1..20).contains(&y)));
+                assert!(game.goal_reachable() && !game.is_over(), "{} seed {}", pattern, seed);
+            }
+        }
+        assert!(dealt(Goal::AnyEdge, Pattern::Empty, 200, 0).blocker.blocked_squares.is_empty());
+    }
+
+    #[test]
+    fn symmetric_blocks_are_mirrored() {
+        for seed in 0..10 {
+            let game = dealt(Goal::AnyEdge, Pattern::Symmetric, 40, seed);
+            let blocks = &game.blocker.blocked_squares;
+            assert_eq!(blocks.len(), 40);
+            assert!(blocks.iter().all(|&(x, y)| blocks.contains(&(20 - y, x))));
+
+            let game = dealt(Goal::TopRow, Pattern::Symmetric, 40, seed);
+            let blocks = &game.blocker.blocked_squares;
+            assert!(blocks.iter().all(|&(x, y)| blocks.contains(&(20 - x, y))));
+        }
+    }
+}